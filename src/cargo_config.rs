@@ -0,0 +1,184 @@
+//! Reading Cargo's `.cargo/config.toml` files.
+//!
+//! See <https://doc.rust-lang.org/cargo/reference/config.html>.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    toml::{Toml, Value},
+    Result,
+};
+
+/// The Cargo settings that affect where artifacts end up and where packages are published to.
+#[derive(Debug, Default)]
+pub struct CargoConfig {
+    /// `build.target`: the targets that Cargo builds for when no `--target` is passed.
+    pub build_targets: Vec<String>,
+    /// `build.target-dir`, resolved against the directory containing `.cargo`.
+    pub target_dir: Option<PathBuf>,
+    /// `registry.default`: the registry `cargo publish` uses when no `--registry` is passed.
+    pub default_registry: Option<String>,
+    /// Index URLs of the registries declared in `[registries]`.
+    pub registries: BTreeMap<String, String>,
+    /// `[alias]`, with string aliases split at whitespace like Cargo does.
+    pub aliases: BTreeMap<String, Vec<String>>,
+}
+
+impl CargoConfig {
+    /// Loads the Cargo configuration that applies to `cwd`.
+    ///
+    /// Like Cargo, this reads `.cargo/config.toml` in `cwd` and all of its parent directories,
+    /// followed by `$CARGO_HOME/config.toml`. Files closer to `cwd` take precedence.
+    pub fn load(cwd: &Path) -> Result<Self> {
+        let mut files = Vec::new();
+        for dir in cwd.ancestors() {
+            // The configuration of the machine running the tests shouldn't affect them.
+            if cfg!(test) && !dir.starts_with(env!("CARGO_MANIFEST_DIR")) {
+                break;
+            }
+            files.extend(config_file(&dir.join(".cargo")));
+        }
+        if !cfg!(test) {
+            if let Some(file) = cargo_home().and_then(|home| config_file(&home)) {
+                if !files.contains(&file) {
+                    files.push(file);
+                }
+            }
+        }
+
+        let mut config = Self::default();
+        for file in files.iter().rev() {
+            config.merge(file)?;
+        }
+        Ok(config)
+    }
+
+    /// Applies the settings from the config file at `path` on top of the current ones.
+    fn merge(&mut self, path: &Path) -> Result<()> {
+        let src = fs::read_to_string(path)?;
+        let toml = Toml::parse_file(&src, path)?;
+        // Relative paths are relative to the directory containing `.cargo`.
+        let base = path
+            .parent()
+            .and_then(Path::parent)
+            .unwrap_or(Path::new(""));
+        let string = |path: &[&str]| -> Result<Option<String>> {
+            match toml.get_path(path) {
+                Ok(_) => Ok(Some(toml.get_str(path)?.to_string())),
+                Err(_) => Ok(None),
+            }
+        };
+        let string_list = |path: &[&str], value: &Value| -> Result<Vec<String>> {
+            match value {
+                Value::Str(s) => Ok(s.split_whitespace().map(String::from).collect()),
+                _ => match value.as_str_array() {
+                    Some(list) => Ok(list.into_iter().map(String::from).collect()),
+                    None => {
+                        let msg = format!(
+                            "`{}` must be a string or an array of strings, but is a {}",
+                            path.join("."),
+                            value.type_name()
+                        );
+                        Err(toml.error(path, msg).into())
+                    }
+                },
+            }
+        };
+
+        if let Ok(target) = toml.get_path(&["build", "target"]) {
+            self.build_targets = string_list(&["build", "target"], target)?;
+        }
+        if let Some(dir) = string(&["build", "target-dir"])? {
+            self.target_dir = Some(base.join(dir));
+        }
+        if let Some(registry) = string(&["registry", "default"])? {
+            self.default_registry = Some(registry);
+        }
+        if let Ok(Value::Table(registries)) = toml.get_path(&["registries"]) {
+            for (name, _) in registries.iter() {
+                if let Some(index) = string(&["registries", name, "index"])? {
+                    self.registries.insert(name.to_string(), index);
+                }
+            }
+        }
+        if let Ok(Value::Table(aliases)) = toml.get_path(&["alias"]) {
+            for (name, value) in aliases.iter() {
+                let args = string_list(&["alias", name], value)?;
+                self.aliases.insert(name.to_string(), args);
+            }
+        }
+        Ok(())
+    }
+
+    /// Locates a build artifact that was specified relative to the default `target` directory.
+    ///
+    /// With `build.target-dir` set, Cargo puts artifacts somewhere else, and with `build.target`
+    /// set, it puts them in a subdirectory named after the target. Paths that exist as given, or
+    /// that don't start with `target/`, are returned unchanged.
+    pub fn artifact_path(&self, path: &Path) -> PathBuf {
+        let Ok(rest) = path.strip_prefix("target") else {
+            return path.to_path_buf();
+        };
+        if path.exists() {
+            return path.to_path_buf();
+        }
+
+        let mut out = self.target_dir.clone().unwrap_or_else(|| "target".into());
+        // With multiple targets, there's no way to tell which one is meant.
+        if let [target] = &*self.build_targets {
+            if !rest.starts_with(target) {
+                out.push(target);
+            }
+        }
+        out.join(rest)
+    }
+}
+
+fn config_file(dir: &Path) -> Option<PathBuf> {
+    // `config` without extension is the legacy name, which Cargo still reads.
+    ["config.toml", "config"]
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+fn cargo_home() -> Option<PathBuf> {
+    match env::var_os("CARGO_HOME") {
+        Some(home) => Some(home.into()),
+        None => env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_artifact_path() {
+        let mut config = CargoConfig::default();
+        let path = Path::new("target/release/tool");
+        assert_eq!(config.artifact_path(path), path);
+
+        config.build_targets = vec!["x86_64-unknown-linux-musl".into()];
+        assert_eq!(
+            config.artifact_path(path),
+            Path::new("target/x86_64-unknown-linux-musl/release/tool"),
+        );
+        let path = Path::new("target/x86_64-unknown-linux-musl/release/tool");
+        assert_eq!(config.artifact_path(path), path);
+
+        config.target_dir = Some("/tmp/build".into());
+        assert_eq!(
+            config.artifact_path(Path::new("target/release/tool")),
+            Path::new("/tmp/build/x86_64-unknown-linux-musl/release/tool"),
+        );
+        assert_eq!(
+            config.artifact_path(Path::new("dist/tool")),
+            Path::new("dist/tool")
+        );
+    }
+}
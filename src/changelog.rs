@@ -0,0 +1,907 @@
+//! Changelog structure checks and edits.
+
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    diagnostic::{Diagnostic, Span},
+    markdown::{skip_blank_line, Heading, Markdown, Rewrite},
+    semver::Version,
+    toml::{parse_date, Date},
+    Result,
+};
+
+/// A heading that introduces the changes of a specific version.
+struct Release<'a> {
+    version: Version,
+    /// The rest of the title, so that releases of different packages in a shared workspace
+    /// changelog (like `## foo v1.0.0` and `## bar v1.0.0`) can be told apart.
+    package: String,
+    date: Option<&'a str>,
+}
+
+impl<'a> Release<'a> {
+    fn parse(heading: Heading<'a>) -> Option<Self> {
+        if is_unreleased(heading.title) {
+            return None;
+        }
+        // The version comes first, or right after the package name. Other headings that mention
+        // a version (like `### Requires Rust 1.80.0`) aren't releases.
+        let mut words = heading.title.split_whitespace();
+        let version = match Version::find_all(words.next()?).pop() {
+            Some(version) => version,
+            None => Version::find_all(words.next()?).pop()?,
+        };
+
+        let mut package = Vec::new();
+        let mut date = None;
+        for word in heading.title.split_whitespace() {
+            let trimmed = word
+                .trim_start_matches(['[', '('])
+                .trim_end_matches([']', ')', ':', ',']);
+            if looks_like_date(trimmed) {
+                date = Some(trimmed);
+            } else if !Version::find_all(word).is_empty() || trimmed.chars().all(|c| c == '-') {
+                // Versions and separators aren't part of the package name.
+            } else {
+                package.push(trimmed.to_lowercase());
+            }
+        }
+
+        Some(Self {
+            version,
+            package: package.join(" "),
+            date,
+        })
+    }
+}
+
+/// Whether the heading is for the `Unreleased` section of the "Keep a Changelog" format.
+///
+/// The title has to be just `Unreleased`, optionally in brackets or as a link.
+pub fn is_unreleased(title: &str) -> bool {
+    let title = title.trim();
+    let title = match title.split_once("](") {
+        Some((text, _)) => text.strip_prefix('[').unwrap_or(text),
+        None => (title
+            .strip_prefix('[')
+            .and_then(|title| title.strip_suffix(']')))
+        .unwrap_or(title),
+    };
+    title.eq_ignore_ascii_case("unreleased")
+}
+
+fn looks_like_date(word: &str) -> bool {
+    word.len() == 10
+        && word.bytes().enumerate().all(|(i, b)| {
+            if i == 4 || i == 7 {
+                b == b'-'
+            } else {
+                b.is_ascii_digit()
+            }
+        })
+}
+
+/// Checks the structure of a changelog.
+///
+/// - Version headings must all be at the same level (sub-headings that mention the same version
+///   as their section are fine).
+/// - Versions must be listed in descending order, without duplicates.
+/// - Version sections must not be empty.
+/// - Dates in version headings must be valid, and must not increase further down.
+pub fn lint(changelog: &str, path: &Path) -> Vec<Diagnostic> {
+    let headings = Markdown(changelog).headings();
+    let error = |heading: &Heading<'_>, msg: String| {
+        Diagnostic::new(changelog, Span::new(heading.start, heading.end), msg).with_file(Some(path))
+    };
+
+    // Version headings are expected at the level most of them use.
+    let mut counts = [0; 7];
+    for heading in &headings {
+        if Release::parse(*heading).is_some() {
+            counts[usize::from(heading.level)] += 1;
+        }
+    }
+    let Some(level) = (1..=6u8)
+        .rev()
+        .max_by_key(|&level| counts[usize::from(level)])
+    else {
+        return Vec::new();
+    };
+    if counts[usize::from(level)] == 0 {
+        return Vec::new();
+    }
+    let hashes = "#".repeat(level.into());
+
+    let mut errors = Vec::new();
+    let mut current: Option<Release<'_>> = None;
+    let mut seen: Vec<(String, Version)> = Vec::new();
+    let mut prev_date: Option<(Date, Version)> = None;
+    for (i, heading) in headings.iter().enumerate() {
+        if heading.level == level {
+            current = None;
+        }
+        let Some(release) = Release::parse(*heading) else {
+            continue;
+        };
+        let version = &release.version;
+
+        if heading.level > level {
+            let nested_in_own_section = current
+                .as_ref()
+                .is_some_and(|current| Version::find_all(heading.title).contains(&current.version));
+            if !nested_in_own_section {
+                errors.push(error(
+                    heading,
+                    format!(
+                        "heading for {version} is nested in another section (version headings should use `{hashes}`)"
+                    ),
+                ));
+            }
+            continue;
+        }
+        if heading.level < level {
+            errors.push(error(
+                heading,
+                format!(
+                    "heading for {version} should use `{hashes}`, like the other version headings"
+                ),
+            ));
+            continue;
+        }
+
+        let end = headings[i + 1..]
+            .iter()
+            .find(|next| next.level <= level)
+            .map_or(changelog.len(), |next| next.start);
+        if changelog[heading.end..end].trim().is_empty() {
+            errors.push(error(heading, format!("section for {version} is empty")));
+        }
+
+        let mut same_package = seen
+            .iter()
+            .filter(|(package, _)| *package == release.package)
+            .map(|(_, version)| version);
+        if same_package.clone().any(|seen| seen == version) {
+            errors.push(error(heading, format!("duplicate heading for {version}")));
+        } else if let Some(previous) = same_package.next_back().filter(|prev| *prev < version) {
+            errors.push(error(
+                heading,
+                format!("{version} is listed below {previous}, but versions should be in descending order"),
+            ));
+        }
+        seen.push((release.package.clone(), version.clone()));
+
+        if let Some(date) = release.date {
+            match parse_date(date) {
+                None => errors.push(error(heading, format!("invalid date `{date}`"))),
+                Some(date) => {
+                    if let Some((prev, prev_version)) = &prev_date {
+                        if date > *prev {
+                            errors.push(error(
+                                heading,
+                                format!("{version} is dated {date}, which is after the date of {prev_version} ({prev}) above it"),
+                            ));
+                        }
+                    }
+                    prev_date = Some((date, version.clone()));
+                }
+            }
+        }
+
+        current = Some(release);
+    }
+    errors
+}
+
+/// Turns the `Unreleased` section into a section for `version`, released on `date`, and adds a
+/// new, empty `Unreleased` section above it.
+///
+/// If `package` is given, the new heading is prefixed with it, so that the section can be told
+/// apart from those of other packages sharing the changelog.
+pub fn promote_unreleased(
+    changelog: &str,
+    package: Option<&str>,
+    version: &Version,
+    date: Date,
+) -> Result<String> {
+    let headings = Markdown(changelog).headings();
+    let Some(index) = headings.iter().position(|h| is_unreleased(h.title)) else {
+        return Err("changelog has no `Unreleased` section".into());
+    };
+    let unreleased = &headings[index];
+    let end = headings[index + 1..]
+        .iter()
+        .find(|next| next.level <= unreleased.level)
+        .map_or(changelog.len(), |next| next.start);
+    if changelog[unreleased.end..end].trim().is_empty() {
+        return Err("the `Unreleased` section is empty".into());
+    }
+    if has_release(&headings, package, version) {
+        return Err(format!("changelog already has a section for {version}").into());
+    }
+
+    // Follow the style of the existing headings.
+    let v = if uses_v_prefix(&headings) { "v" } else { "" };
+    let mut title = format!("{v}{version}");
+    if unreleased.title.starts_with('[') {
+        title = format!("[{title}]");
+    }
+    if let Some(package) = package {
+        title = format!("{package} {title}");
+    }
+    let newline = if changelog[..unreleased.end].ends_with("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    let mut out = changelog[..unreleased.end].to_string();
+    if !out.ends_with('\n') {
+        out += newline;
+    }
+    out += newline;
+    out += &format!(
+        "{} {title} - {date}{newline}",
+        "#".repeat(unreleased.level.into())
+    );
+    out += &changelog[unreleased.end..];
+    Ok(out)
+}
+
+/// Adds a section for `version`, released on `date`, containing `notes`.
+///
+/// The section is inserted above the newest older release, at the level of the existing release
+/// headings. If `package` is given, the heading is prefixed with it, like in
+/// [`promote_unreleased`].
+pub fn add_release(
+    changelog: &str,
+    package: Option<&str>,
+    version: &Version,
+    date: Date,
+    notes: &str,
+) -> Result<String> {
+    let headings = Markdown(changelog).headings();
+    if has_release(&headings, package, version) {
+        return Err(format!("changelog already has a section for {version}").into());
+    }
+    let releases = headings
+        .iter()
+        .filter(|heading| Release::parse(**heading).is_some())
+        .copied()
+        .collect::<Vec<_>>();
+    let unreleased = headings.iter().find(|h| is_unreleased(h.title));
+    let level = match (releases.first(), unreleased) {
+        (Some(heading), _) | (None, Some(heading)) => heading.level,
+        (None, None) => 2,
+    };
+    // Keep the releases in descending order.
+    let next = releases.iter().find(|heading| {
+        heading.level == level
+            && Release::parse(**heading).is_some_and(|release| release.version < *version)
+    });
+
+    // Follow the style of the existing headings.
+    let v = if uses_v_prefix(&headings) { "v" } else { "" };
+    let mut title = format!("{v}{version}");
+    if releases
+        .first()
+        .is_some_and(|heading| heading.title.starts_with('['))
+    {
+        title = format!("[{title}]");
+    }
+    if let Some(package) = package {
+        title = format!("{package} {title}");
+    }
+    let newline = if changelog.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let section = format!(
+        "{} {title} - {date}{newline}{newline}{}{newline}",
+        "#".repeat(level.into()),
+        notes.lines().collect::<Vec<_>>().join(newline),
+    );
+
+    let mut out = String::new();
+    match next {
+        Some(heading) => {
+            out += &changelog[..heading.start];
+            out += &section;
+            out += newline;
+            out += &changelog[heading.start..];
+        }
+        None => {
+            out += changelog.trim_end();
+            if !out.is_empty() {
+                out += newline;
+                out += newline;
+            }
+            out += &section;
+        }
+    }
+    Ok(out)
+}
+
+/// Whether there already is a section for `version` (of `package`, if given).
+fn has_release(headings: &[Heading<'_>], package: Option<&str>, version: &Version) -> bool {
+    headings.iter().any(|heading| {
+        Release::parse(*heading).is_some_and(|release| {
+            release.version == *version
+                && package.is_none_or(|name| release.package == name.to_lowercase())
+        })
+    })
+}
+
+fn uses_v_prefix(headings: &[Heading<'_>]) -> bool {
+    headings.iter().any(|heading| {
+        heading.title.split_whitespace().any(|word| {
+            let word = word.trim_start_matches(['[', '(']);
+            (word.starts_with(['v', 'V'])) && !Version::find_all(word).is_empty()
+        })
+    })
+}
+
+/// Returns the current date (in UTC).
+pub fn today() -> Date {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is set before 1970")
+        .as_secs();
+    date_from_days(secs / 86400)
+}
+
+/// Converts a number of days since 1970-01-01 to a date.
+///
+/// See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn date_from_days(days: u64) -> Date {
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    Date {
+        year: year as u16,
+        month: month as u8,
+        day: day as u8,
+    }
+}
+
+/// Extracts the notes of `package` from a release section that may have sub-sections for
+/// individual packages, like `### foo` and `### foo-derive` below `## v1.0.0`.
+///
+/// `packages` are the names of all packages. The notes consist of everything in the section except
+/// the sub-sections of other packages, and the heading of the package's own sub-section. Returns
+/// `None` if nothing is left.
+pub fn package_notes(section: &str, package: &str, packages: &[&str]) -> Option<String> {
+    let headings = Markdown(section).headings();
+    let package_of = |heading: &Heading<'_>| {
+        // Allow some decoration, like `### `foo` v1.0.0`.
+        let title = heading
+            .title
+            .split_whitespace()
+            .filter(|word| Version::find_all(word).is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let title = title.trim_matches(['`', '*', '[', ']', ':']);
+        packages
+            .iter()
+            .copied()
+            .find(|name| name.eq_ignore_ascii_case(title))
+    };
+    let Some(level) = headings
+        .iter()
+        .filter(|heading| package_of(heading).is_some())
+        .map(|heading| heading.level)
+        .min()
+    else {
+        let notes = section.trim();
+        return (!notes.is_empty()).then(|| notes.to_string());
+    };
+
+    let mut notes = String::new();
+    let mut copied = 0;
+    for (i, heading) in headings.iter().enumerate() {
+        if heading.level != level {
+            continue;
+        }
+        let Some(name) = package_of(heading) else {
+            continue;
+        };
+        notes += &section[copied..heading.start];
+        if name == package {
+            let rest = &section[heading.end..];
+            copied = section.len() - rest.trim_start_matches(['\r', '\n']).len();
+        } else {
+            copied = headings[i + 1..]
+                .iter()
+                .find(|next| next.level <= level)
+                .map_or(section.len(), |next| next.start);
+        }
+    }
+    notes += &section[copied..];
+    let notes = notes.trim();
+    (!notes.is_empty()).then(|| notes.to_string())
+}
+
+/// Removes what's only meant for maintainers from release notes: HTML comments, and blocks
+/// between the marker lines given in `excluded` as `(start, end)` pairs.
+pub fn sanitize(notes: &str, excluded: &[(String, String)]) -> String {
+    let mut kept = String::new();
+    let mut end_marker = None;
+    let mut pos = 0;
+    while pos < notes.len() {
+        let start = pos;
+        pos = notes[start..]
+            .find('\n')
+            .map_or(notes.len(), |i| start + i + 1);
+        let line = notes[start..pos].trim();
+        match end_marker {
+            Some(end) if line == end => {
+                end_marker = None;
+                pos = skip_blank_line(notes, pos, &kept);
+            }
+            Some(_) => {}
+            None => match excluded.iter().find(|(start, _)| line == start) {
+                Some((_, end)) => end_marker = Some(&**end),
+                None => kept += &notes[start..pos],
+            },
+        }
+    }
+    Markdown(&kept).remove_html_comments().trim().to_string()
+}
+
+/// Makes links in release notes work outside of the repository.
+///
+/// Relative links are turned into links to the files at `commit`, resolved against `dir` (the
+/// changelog's directory, relative to the repository root). Issue references like `#12` and
+/// `GH-12` are turned into links to the issue.
+pub fn absolutize_links(notes: &str, repository: &str, commit: &str, dir: &str) -> String {
+    let repository = repository.trim_end_matches('/');
+    let repository = repository.strip_suffix(".git").unwrap_or(repository);
+    Markdown(notes).rewrite_links(|rewrite| match rewrite {
+        Rewrite::Destination { url, image } => {
+            if url.is_empty() || url.starts_with('#') || url.starts_with("//") || has_scheme(url) {
+                return None;
+            }
+            let (path, suffix) = url.split_at(url.find(['?', '#']).unwrap_or(url.len()));
+            let path = join_path(dir, path)?;
+            // Images have to link to the raw file to be displayed.
+            let kind = if image { "raw" } else { "blob" };
+            Some(format!("{repository}/{kind}/{commit}/{path}{suffix}"))
+        }
+        Rewrite::Issue { reference, number } => {
+            Some(format!("[{reference}]({repository}/issues/{number})"))
+        }
+    })
+}
+
+fn has_scheme(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// Resolves a relative `path` against `dir`, or against the repository root if it starts with a
+/// `/`. Returns `None` if the path points outside of the repository.
+fn join_path(dir: &str, path: &str) -> Option<String> {
+    let mut segments = Vec::new();
+    if !path.starts_with('/') {
+        segments.extend(dir.split('/').filter(|s| !s.is_empty()));
+    }
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some(segments.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    #[track_caller]
+    fn check(changelog: &str, expect: expect_test::Expect) {
+        let errors = lint(changelog, Path::new("CHANGELOG.md"))
+            .iter()
+            .map(|diag| format!("{}: {}\n", diag.line, diag.message))
+            .collect::<String>();
+        expect.assert_eq(&errors);
+    }
+
+    #[test]
+    fn test_lint_valid() {
+        check(
+            "# Changelog
+
+## [Unreleased]
+
+## [1.1.0] - 2026-02-01
+
+### Added
+
+- Feature.
+
+## [1.1.0-rc.1] - 2026-01-15
+
+- Release candidate.
+
+## [1.0.0] - 2026-01-01
+
+- Initial release.
+",
+            expect![""],
+        );
+        // Shared workspace changelogs have a section per package, and may have sub-headings that
+        // mention the version again.
+        check(
+            "## foo v1.0.0
+
+- foo
+
+## bar v1.0.0
+
+- bar
+
+### bar-derive 1.0.0
+
+- derive
+
+## foo v0.1.0
+
+- foo
+",
+            expect![""],
+        );
+        // Sub-headings that merely mention a version or the word "unreleased" are no releases.
+        check(
+            "## [Unreleased](https://github.com/o/r/compare/v1.1.0...HEAD)
+
+### Notes on unreleased APIs
+
+- a
+
+## [1.1.0] - 2026-02-01
+
+### Requires Rust 1.80.0
+
+- b
+
+### Also requires Rust 1.80.0
+
+- c
+
+## [1.0.0] - 2026-01-01
+
+- d
+",
+            expect![""],
+        );
+    }
+
+    #[test]
+    fn test_lint_errors() {
+        check(
+            "## 0.1.0
+
+- a
+
+## 0.2.0
+
+## 0.1.0 (2026-13-01)
+
+- b
+
+### 0.0.9
+
+- c
+
+# 0.0.1 - 2025-01-01
+
+- d
+
+## 0.0.5 - 2025-01-01
+
+- e
+
+## 0.0.4 - 2025-02-01
+
+- f
+",
+            expect![[r#"
+                5: section for 0.2.0 is empty
+                5: 0.2.0 is listed below 0.1.0, but versions should be in descending order
+                7: duplicate heading for 0.1.0
+                7: invalid date `2026-13-01`
+                11: heading for 0.0.9 is nested in another section (version headings should use `##`)
+                15: heading for 0.0.1 should use `##`, like the other version headings
+                23: 0.0.4 is dated 2025-02-01, which is after the date of 0.0.5 (2025-01-01) above it
+            "#]],
+        );
+    }
+
+    #[test]
+    fn test_date_from_days() {
+        assert_eq!(date_from_days(0).to_string(), "1970-01-01");
+        assert_eq!(date_from_days(11016).to_string(), "2000-02-29");
+        assert_eq!(date_from_days(20742).to_string(), "2026-10-16");
+    }
+
+    #[test]
+    fn test_promote_unreleased() {
+        let date = parse_date("2026-10-16").unwrap();
+        let version = Version::parse("1.2.0").unwrap();
+        let promote = |changelog: &str, package: Option<&str>| {
+            promote_unreleased(changelog, package, &version, date)
+                .unwrap_or_else(|e| format!("error: {e}\n"))
+        };
+
+        expect![[r#"
+            # Changelog
+
+            ## [Unreleased]
+
+            ## [1.2.0] - 2026-10-16
+
+            ### Added
+
+            - Feature.
+
+            ## [1.1.0] - 2026-01-01
+
+            - Old.
+        "#]]
+        .assert_eq(&promote(
+            "# Changelog\n\n## [Unreleased]\n\n### Added\n\n- Feature.\n\n## [1.1.0] - 2026-01-01\n\n- Old.\n",
+            None,
+        ));
+        expect![[r#"
+            ## Unreleased
+
+            ## mypkg v1.2.0 - 2026-10-16
+
+            - Feature.
+
+            ## other v1.2.0
+
+            - Old.
+        "#]]
+        .assert_eq(&promote(
+            "## Unreleased\n\n- Feature.\n\n## other v1.2.0\n\n- Old.\n",
+            Some("mypkg"),
+        ));
+
+        expect![[r#"
+            error: the `Unreleased` section is empty
+        "#]]
+        .assert_eq(&promote("## Unreleased\n\n## v1.1.0\n\n- Old.\n", None));
+        expect![[r#"
+            error: changelog already has a section for 1.2.0
+        "#]]
+        .assert_eq(&promote(
+            "## Unreleased\n\n- New.\n\n## v1.2.0\n\n- Old.\n",
+            None,
+        ));
+        expect![[r#"
+            error: changelog has no `Unreleased` section
+        "#]]
+        .assert_eq(&promote("## v1.1.0\n\n- Old.\n", None));
+    }
+
+    #[test]
+    fn test_add_release() {
+        let date = parse_date("2026-10-16").unwrap();
+        let version = Version::parse("1.2.0").unwrap();
+        let add = |changelog: &str, package: Option<&str>| {
+            add_release(changelog, package, &version, date, "- New.\n- Newer.")
+                .unwrap_or_else(|e| format!("error: {e}\n"))
+        };
+
+        expect![[r#"
+            # Changelog
+
+            ## [Unreleased]
+
+            ## [1.2.0] - 2026-10-16
+
+            - New.
+            - Newer.
+
+            ## [1.1.0] - 2026-01-01
+
+            - Old.
+        "#]]
+        .assert_eq(&add(
+            "# Changelog\n\n## [Unreleased]\n\n## [1.1.0] - 2026-01-01\n\n- Old.\n",
+            None,
+        ));
+        expect![[r#"
+            # Changelog
+
+            ### mypkg v1.2.0 - 2026-10-16
+
+            - New.
+            - Newer.
+
+            ### v1.1.0
+
+            - Old.
+        "#]]
+        .assert_eq(&add("# Changelog\n\n### v1.1.0\n\n- Old.\n", Some("mypkg")));
+        expect![[r#"
+            ## other v2.0.0
+
+            - Other.
+
+            ## mypkg v1.2.0 - 2026-10-16
+
+            - New.
+            - Newer.
+
+            ## mypkg v1.1.0
+
+            - Old.
+        "#]]
+        .assert_eq(&add(
+            "## other v2.0.0\n\n- Other.\n\n## mypkg v1.1.0\n\n- Old.\n",
+            Some("mypkg"),
+        ));
+        expect![[r#"
+            # Changelog
+
+            ## 1.2.0 - 2026-10-16
+
+            - New.
+            - Newer.
+        "#]]
+        .assert_eq(&add("# Changelog\n", None));
+        expect![[r#"
+            ## 1.2.0 - 2026-10-16
+
+            - New.
+            - Newer.
+        "#]]
+        .assert_eq(&add("", None));
+        expect![[r#"
+            error: changelog already has a section for 1.2.0
+        "#]]
+        .assert_eq(&add("## v1.2.0\n\n- Old.\n", None));
+    }
+
+    #[test]
+    fn test_package_notes() {
+        let section = "Requires Rust 1.80.
+
+### foo
+
+- Added `foo::bar`.
+
+#### Details
+
+More about `foo::bar`.
+
+### `foo-derive` v0.5.0
+
+- Fixed `#[derive(Foo)]`.
+
+### Contributors
+
+Thanks!
+";
+        let packages = ["foo", "foo-derive", "foo-utils"];
+        let notes = |package| {
+            package_notes(section, package, &packages).unwrap_or_else(|| "<none>".to_string())
+        };
+        expect![[r#"
+            Requires Rust 1.80.
+
+            - Added `foo::bar`.
+
+            #### Details
+
+            More about `foo::bar`.
+
+            ### Contributors
+
+            Thanks!"#]]
+        .assert_eq(&notes("foo"));
+        expect![[r#"
+            Requires Rust 1.80.
+
+            - Fixed `#[derive(Foo)]`.
+
+            ### Contributors
+
+            Thanks!"#]]
+        .assert_eq(&notes("foo-derive"));
+        expect![[r#"
+            Requires Rust 1.80.
+
+            ### Contributors
+
+            Thanks!"#]]
+        .assert_eq(&notes("foo-utils"));
+
+        assert_eq!(
+            package_notes("### foo\n\n- Foo.\n", "bar", &["foo", "bar"]),
+            None
+        );
+        assert_eq!(
+            package_notes("### Added\n\n- Thing.\n", "foo", &["foo"]).as_deref(),
+            Some("### Added\n\n- Thing."),
+        );
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let excluded = [(
+            "<!-- internal -->".to_string(),
+            "<!-- /internal -->".to_string(),
+        )];
+        let notes = "- Added `--color`. <!-- TODO: mention `NO_COLOR` -->
+
+<!-- internal -->
+- [ ] Update the website.
+<!-- /internal -->
+
+- Fixed a crash.
+";
+        expect![[r#"
+            - Added `--color`.
+
+            - Fixed a crash."#]]
+        .assert_eq(&sanitize(notes, &excluded));
+    }
+
+    #[test]
+    fn test_absolutize_links() {
+        let notes = r#"- Fixes #12 and GH-3, but not a#1, &#35; or #12a.
+- See [the docs](docs/guide.md#usage), [the root](/README.md), [up](../LICENSE "License")
+  and ![logo](<img/logo 1.png>).
+- [Absolute](https://example.com/#1), [anchor](#usage), <https://example.com/#5> and
+  https://github.com/user/repo/pull/4#issuecomment-5 stay.
+- `#1` and `[code](code.md)` too.
+- Ünïcode — \é and [ü](ü.md) (#5).
+
+```
+#7 [block](block.md)
+```
+"#;
+        expect![[r#"
+            - Fixes [#12](https://github.com/user/repo/issues/12) and [GH-3](https://github.com/user/repo/issues/3), but not a#1, &#35; or #12a.
+            - See [the docs](https://github.com/user/repo/blob/abc123/crates/foo/docs/guide.md#usage), [the root](https://github.com/user/repo/blob/abc123/README.md), [up](https://github.com/user/repo/blob/abc123/crates/LICENSE "License")
+              and ![logo](<https://github.com/user/repo/raw/abc123/crates/foo/img/logo 1.png>).
+            - [Absolute](https://example.com/#1), [anchor](#usage), <https://example.com/#5> and
+              https://github.com/user/repo/pull/4#issuecomment-5 stay.
+            - `#1` and `[code](code.md)` too.
+            - Ünïcode — \é and [ü](https://github.com/user/repo/blob/abc123/crates/foo/ü.md) ([#5](https://github.com/user/repo/issues/5)).
+
+            ```
+            #7 [block](block.md)
+            ```
+        "#]]
+        .assert_eq(&absolutize_links(
+            notes,
+            "https://github.com/user/repo.git",
+            "abc123",
+            "crates/foo",
+        ));
+    }
+}
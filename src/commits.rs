@@ -0,0 +1,142 @@
+//! Release notes generated from [Conventional Commits].
+//!
+//! [Conventional Commits]: https://www.conventionalcommits.org/
+
+use std::fmt::Write;
+
+/// The `git log` argument that makes it print commits in the format [`release_notes`] expects:
+/// every commit starts with a record separator, followed by its abbreviated hash and message.
+pub const LOG_FORMAT: &str = "--format=%x1e%h%n%B";
+
+/// The sections of the release notes, in order.
+const GROUPS: [&str; 4] = ["Breaking Changes", "Features", "Bug Fixes", "Performance"];
+
+/// Builds release notes from `git log` output, grouping the commits by type.
+///
+/// Only breaking changes, features, fixes and performance improvements are listed. Returns `None`
+/// if there are no such commits.
+pub fn release_notes(log: &str) -> Option<String> {
+    let mut groups: [Vec<String>; GROUPS.len()] = Default::default();
+    for record in log.split('\x1e') {
+        let Some((hash, message)) = record.split_once('\n') else {
+            continue;
+        };
+        let Some(commit) = Commit::parse(message) else {
+            continue;
+        };
+        let group = match &*commit.kind {
+            _ if commit.breaking => 0,
+            "feat" => 1,
+            "fix" => 2,
+            "perf" => 3,
+            _ => continue,
+        };
+        let entry = match commit.scope {
+            Some(scope) => format!("- **{scope}:** {} ({hash})", commit.description),
+            None => format!("- {} ({hash})", commit.description),
+        };
+        groups[group].push(entry);
+    }
+
+    let mut notes = String::new();
+    for (title, entries) in GROUPS.iter().zip(&groups) {
+        if entries.is_empty() {
+            continue;
+        }
+        if !notes.is_empty() {
+            notes += "\n";
+        }
+        writeln!(notes, "### {title}\n").ok();
+        for entry in entries {
+            writeln!(notes, "{entry}").ok();
+        }
+    }
+    (!notes.is_empty()).then(|| notes.trim_end().to_string())
+}
+
+/// A commit message like `feat(parser)!: support comments`.
+struct Commit<'a> {
+    /// The commit type, in lowercase.
+    kind: String,
+    scope: Option<&'a str>,
+    /// Whether the type is followed by a `!`, or there is a `BREAKING CHANGE:` footer.
+    breaking: bool,
+    description: &'a str,
+}
+
+impl<'a> Commit<'a> {
+    fn parse(message: &'a str) -> Option<Self> {
+        let subject = message.lines().next()?;
+        let (prefix, description) = subject.split_once(':')?;
+        let (prefix, breaking) = match prefix.strip_suffix('!') {
+            Some(prefix) => (prefix, true),
+            None => (prefix, false),
+        };
+        let (kind, scope) = match prefix.split_once('(') {
+            Some((kind, scope)) => (kind, Some(scope.strip_suffix(')')?.trim())),
+            None => (prefix, None),
+        };
+        let description = description.trim();
+        if kind.is_empty()
+            || !kind.chars().all(|c| c.is_ascii_alphabetic())
+            || description.is_empty()
+        {
+            return None;
+        }
+
+        let breaking = breaking
+            || message.lines().skip(1).any(|line| {
+                line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:")
+            });
+        Some(Self {
+            kind: kind.to_ascii_lowercase(),
+            scope: scope.filter(|scope| !scope.is_empty()),
+            breaking,
+            description,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    #[test]
+    fn test_release_notes() {
+        let log = [
+            "\x1ea1a1a1a\nfeat(parser): support comments\n\nLonger description.\n",
+            "\x1eb2b2b2b\nfix: don't crash on empty input\n",
+            "\x1ec3c3c3c\nchore: update dependencies\n",
+            "\x1ed4d4d4d\nrefactor!: rename `Config` to `Settings`\n",
+            "\x1ee5e5e5e\nFeat: remove the old API\n\nBREAKING CHANGE: `old` is gone.\n",
+            "\x1ef6f6f6f\nperf(lexer): avoid allocations\n",
+            "\x1e0707070\nMerge branch 'main' into feature\n",
+            "\x1e0808080\nfix(): handle `a: b` in values",
+        ]
+        .concat();
+        expect![[r#"
+            ### Breaking Changes
+
+            - rename `Config` to `Settings` (d4d4d4d)
+            - remove the old API (e5e5e5e)
+
+            ### Features
+
+            - **parser:** support comments (a1a1a1a)
+
+            ### Bug Fixes
+
+            - don't crash on empty input (b2b2b2b)
+            - handle `a: b` in values (0808080)
+
+            ### Performance
+
+            - **lexer:** avoid allocations (f6f6f6f)"#]]
+        .assert_eq(&release_notes(&log).unwrap());
+
+        assert_eq!(release_notes("\x1ea1a1a1a\nchore: bump version\n"), None);
+        assert_eq!(release_notes(""), None);
+    }
+}
@@ -0,0 +1,177 @@
+//! Errors pointing at a location in a file.
+
+use std::{
+    env, fmt,
+    path::{Path, PathBuf},
+};
+
+/// A byte range in a source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
+/// An error message with the file, line and column it refers to.
+///
+/// The [`fmt::Display`] impl renders the offending line with the span underlined, and
+/// [`Diagnostic::annotation`] produces a GitHub Actions workflow command that highlights the
+/// line in pull requests.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub file: Option<PathBuf>,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column number, in characters.
+    pub column: usize,
+    /// The source line containing the start of the span.
+    snippet: String,
+    /// Number of characters to underline.
+    width: usize,
+}
+
+impl Diagnostic {
+    pub fn new(src: &str, span: Span, message: impl Into<String>) -> Self {
+        // Spans that don't fall on character boundaries are widened to whole characters.
+        let mut start = span.start.min(src.len());
+        while !src.is_char_boundary(start) {
+            start -= 1;
+        }
+        let before = &src[..start];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line_end = src[start..].find('\n').map_or(src.len(), |i| start + i);
+        let snippet = src[line_start..line_end].trim_end_matches('\r');
+        let mut end = span.end.clamp(start, line_start + snippet.len());
+        while !src.is_char_boundary(end) {
+            end += 1;
+        }
+
+        Self {
+            message: message.into(),
+            file: None,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            snippet: snippet.to_string(),
+            width: src[start..end].chars().count().max(1),
+        }
+    }
+
+    pub fn with_file(mut self, file: Option<&Path>) -> Self {
+        self.file = file.map(Path::to_path_buf);
+        self
+    }
+
+    /// Formats the diagnostic as a GitHub Actions `::error` workflow command.
+    pub fn annotation(&self) -> String {
+        let mut out = String::from("::error ");
+        if let Some(file) = &self.file {
+            // Property values end at `,` and `:`, so those have to be escaped as well.
+            let file = display_file(file)
+                .replace('%', "%25")
+                .replace('\r', "%0D")
+                .replace('\n', "%0A")
+                .replace(',', "%2C")
+                .replace(':', "%3A");
+            out += &format!("file={file},");
+        }
+        out += &format!(
+            "line={},col={},endColumn={}::{}",
+            self.line,
+            self.column,
+            // `endColumn` is inclusive.
+            (self.column + self.width - 1).max(self.column),
+            // Workflow commands end at the first newline, so they have to be escaped.
+            self.message
+                .replace('%', "%25")
+                .replace('\r', "%0D")
+                .replace('\n', "%0A"),
+        );
+        out
+    }
+}
+
+/// Makes `file` relative to the working directory, which is the repository root on CI.
+fn display_file(file: &Path) -> String {
+    let relative = env::current_dir()
+        .ok()
+        .and_then(|cwd| file.strip_prefix(cwd).ok().map(Path::to_path_buf));
+    relative.as_deref().unwrap_or(file).display().to_string()
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let gutter = self.line.to_string().len();
+        writeln!(f, "error: {}", self.message)?;
+        write!(f, "{:gutter$}--> ", "")?;
+        if let Some(file) = &self.file {
+            write!(f, "{}:", display_file(file))?;
+        }
+        writeln!(f, "{}:{}", self.line, self.column)?;
+        writeln!(f, "{:gutter$} |", "")?;
+        writeln!(f, "{} | {}", self.line, self.snippet)?;
+        write!(
+            f,
+            "{:gutter$} | {:indent$}{}",
+            "",
+            "",
+            "^".repeat(self.width),
+            indent = self.column - 1
+        )
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let src = "[package]\nname = \"pkg\"\nversion = \"1.0\n";
+        let diag = Diagnostic::new(src, Span::new(33, 37), "unterminated string")
+            .with_file(Some(Path::new("crate/Cargo.toml")));
+        expect![[r#"
+            error: unterminated string
+             --> crate/Cargo.toml:3:11
+              |
+            3 | version = "1.0
+              |           ^^^^
+        "#]]
+        .assert_eq(&format!("{diag}\n"));
+        expect![[
+            r#"::error file=crate/Cargo.toml,line=3,col=11,endColumn=14::unterminated string"#
+        ]]
+        .assert_eq(&diag.annotation());
+
+        let diag = Diagnostic::new(src, Span::new(src.len(), src.len()), "100%\nfailure");
+        expect![[r#"::error line=4,col=1,endColumn=1::100%25%0Afailure"#]]
+            .assert_eq(&diag.annotation());
+
+        let diag = Diagnostic::new(src, Span::new(0, 1), "bad file name")
+            .with_file(Some(Path::new("a,b:c%d.toml")));
+        expect![[r#"::error file=a%2Cb%3Ac%25d.toml,line=1,col=1,endColumn=1::bad file name"#]]
+            .assert_eq(&diag.annotation());
+
+        // A span ending inside a multibyte character covers all of it.
+        let src = "name = \"é\"";
+        let diag = Diagnostic::new(src, Span::new(8, 9), "non-ASCII");
+        expect![[r#"
+            error: non-ASCII
+             --> 1:9
+              |
+            1 | name = "é"
+              |         ^
+        "#]]
+        .assert_eq(&format!("{diag}\n"));
+    }
+}
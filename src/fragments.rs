@@ -0,0 +1,167 @@
+//! Changelog fragments.
+//!
+//! Instead of editing the changelog directly, every change can add a file to a fragment directory
+//! (`changelog.d` by default). The fragments are assembled into the release notes, and written into
+//! the changelog by `sludge-cicd changelog assemble`. Unlike edits to the same changelog, fragments
+//! added by concurrent pull requests don't conflict with each other.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::Result;
+
+/// The fragment directory that is used when `changelog-fragments` isn't configured.
+pub const DEFAULT_DIR: &str = "changelog.d";
+
+/// The sections of the "Keep a Changelog" format, which fragments are sorted into.
+const CATEGORIES: [&str; 6] = [
+    "Added",
+    "Changed",
+    "Deprecated",
+    "Removed",
+    "Fixed",
+    "Security",
+];
+
+/// A file in a fragment directory.
+#[derive(Debug)]
+pub struct Fragment {
+    pub path: PathBuf,
+    /// Index into [`CATEGORIES`].
+    category: Option<usize>,
+    text: String,
+}
+
+/// Reads all fragments in `dir`, sorted by file name.
+///
+/// Fragments are Markdown files named `<name>.<category>.md`, where `<category>` is one of
+/// `added`, `changed`, `deprecated`, `removed`, `fixed` and `security`. Files without a known
+/// category are included without being sorted into a section. A `README.md` is not a fragment.
+pub fn read(dir: &Path) -> Result<Vec<Fragment>> {
+    let mut fragments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let Some(stem) = name.strip_suffix(".md") else {
+            continue;
+        };
+        if !path.is_file() || name.eq_ignore_ascii_case("README.md") {
+            continue;
+        }
+
+        let text = fs::read_to_string(&path)?.trim().to_string();
+        fragments.push(Fragment {
+            category: category(stem),
+            path,
+            text,
+        });
+    }
+    fragments.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(fragments)
+}
+
+/// Determines the category of a fragment from its file name (without the `.md` extension).
+fn category(stem: &str) -> Option<usize> {
+    let (_, category) = stem.rsplit_once('.')?;
+    CATEGORIES
+        .iter()
+        .position(|known| known.eq_ignore_ascii_case(category))
+}
+
+/// Assembles fragments into release notes, with a `###` section per category.
+///
+/// Every fragment becomes a list item, unless it already is a list.
+pub fn assemble(fragments: &[Fragment]) -> String {
+    let mut notes = String::new();
+    let uncategorized = None;
+    for category in [uncategorized]
+        .into_iter()
+        .chain((0..CATEGORIES.len()).map(Some))
+    {
+        let mut items = fragments
+            .iter()
+            .filter(|fragment| fragment.category == category && !fragment.text.is_empty())
+            .peekable();
+        if items.peek().is_none() {
+            continue;
+        }
+
+        if !notes.is_empty() {
+            notes += "\n";
+        }
+        if let Some(category) = category {
+            notes += &format!("### {}\n\n", CATEGORIES[category]);
+        }
+        for fragment in items {
+            notes += &list_item(&fragment.text);
+            notes += "\n";
+        }
+    }
+    notes.trim_end().to_string()
+}
+
+fn list_item(text: &str) -> String {
+    if text.starts_with("- ") || text.starts_with("* ") {
+        return text.to_string();
+    }
+    let mut item = String::new();
+    for (i, line) in text.lines().enumerate() {
+        item += match (i, line.is_empty()) {
+            (0, _) => "- ",
+            (_, true) => "\n",
+            (_, false) => "\n  ",
+        };
+        item += line;
+    }
+    item
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    fn fragment(name: &str, text: &str) -> Fragment {
+        Fragment {
+            path: name.into(),
+            category: category(name.strip_suffix(".md").unwrap()),
+            text: text.into(),
+        }
+    }
+
+    #[test]
+    fn test_assemble() {
+        let fragments = [
+            fragment("12.fixed.md", "Fixed a crash on empty input."),
+            fragment(
+                "13.added.md",
+                "Added `--color`.\n\nIt takes `auto`, `always` or `never`.",
+            ),
+            fragment("14.md", "Improved the documentation."),
+            fragment("15.Added.md", "- Added `--quiet`.\n- Added `--verbose`."),
+            fragment("16.other.md", "Something else."),
+            fragment("17.security.md", ""),
+        ];
+        expect![[r#"
+            - Improved the documentation.
+            - Something else.
+
+            ### Added
+
+            - Added `--color`.
+
+              It takes `auto`, `always` or `never`.
+            - Added `--quiet`.
+            - Added `--verbose`.
+
+            ### Fixed
+
+            - Fixed a crash on empty input."#]]
+        .assert_eq(&assemble(&fragments));
+    }
+}
@@ -0,0 +1,214 @@
+//! Glob patterns, like those in `[workspace] members`.
+//!
+//! The syntax is that of the `glob` crate used by Cargo: `*` and `?` match any sequence of
+//! characters and any single character within a path component, `[abc]`, `[a-z]` and `[!abc]`
+//! match one of (or none of) a set of characters, and a `**` component matches any number of
+//! directories.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::Result;
+
+/// Returns whether `pattern` contains any special characters.
+pub fn is_pattern(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Finds the existing paths that match `pattern`, which is relative to `base`, in sorted order.
+///
+/// A pattern ending in `/` only matches directories.
+pub fn expand(base: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+    let mut out = Vec::new();
+    expand_components(base.to_path_buf(), &components(pattern), &mut out)?;
+    if pattern.ends_with(['/', '\\']) {
+        out.retain(|path| path.is_dir());
+    }
+    out.sort();
+    out.dedup();
+    Ok(out)
+}
+
+fn components(path: &str) -> Vec<&str> {
+    path.split(['/', '\\'])
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect()
+}
+
+fn expand_components(path: PathBuf, components: &[&str], out: &mut Vec<PathBuf>) -> Result<()> {
+    let Some((first, rest)) = components.split_first() else {
+        if path.exists() {
+            out.push(path);
+        }
+        return Ok(());
+    };
+    if *first == "**" {
+        // Zero directories, or one more directory and still in the `**`.
+        expand_components(path.clone(), rest, out)?;
+        for dir in subdirectories(&path)? {
+            expand_components(dir, components, out)?;
+        }
+        return Ok(());
+    }
+    if !is_pattern(first) {
+        return expand_components(path.join(first), rest, out);
+    }
+
+    let Ok(entries) = fs::read_dir(&path) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name.to_str().is_some_and(|name| matches(first, name)) {
+            expand_components(entry.path(), rest, out)?;
+        }
+    }
+    Ok(())
+}
+
+fn subdirectories(path: &Path) -> Result<Vec<PathBuf>> {
+    let Ok(entries) = fs::read_dir(path) else {
+        return Ok(Vec::new());
+    };
+    let mut dirs = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    Ok(dirs)
+}
+
+/// Returns whether `path`, or a directory containing it, matches `pattern`. Both are relative to
+/// the same directory.
+pub fn matches_path(pattern: &str, path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    matches_prefix(&components(pattern), &components(&path))
+}
+
+fn matches_prefix(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => true,
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| matches_prefix(rest, &path[skip..])),
+        Some((first, rest)) => path
+            .split_first()
+            .is_some_and(|(name, path)| matches(first, name) && matches_prefix(rest, path)),
+    }
+}
+
+/// Matches a single path component against a pattern component.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    matches_chars(&pattern, &name)
+}
+
+fn matches_chars(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| matches_chars(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && matches_chars(rest, &name[1..]),
+        Some(('[', rest)) => {
+            let Some((&c, name_rest)) = name.split_first() else {
+                return false;
+            };
+            let (negated, set) = match rest.split_first() {
+                Some(('!', set)) => (true, set),
+                _ => (false, rest),
+            };
+            // A `]` right at the start is part of the set.
+            let Some(close) = set.iter().skip(1).position(|&c| c == ']').map(|i| i + 1) else {
+                // Unclosed, so the `[` is literal.
+                return c == '[' && matches_chars(rest, name_rest);
+            };
+            let mut found = false;
+            let mut i = 0;
+            while i < close {
+                if i + 2 < close && set[i + 1] == '-' {
+                    found |= (set[i]..=set[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    found |= set[i] == c;
+                    i += 1;
+                }
+            }
+            found != negated && matches_chars(&set[close + 1..], name_rest)
+        }
+        Some((&p, rest)) => name.first() == Some(&p) && matches_chars(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        #[track_caller]
+        fn check(pattern: &str, name: &str, expected: bool) {
+            assert_eq!(matches(pattern, name), expected, "{pattern} {name}");
+        }
+
+        check("foo", "foo", true);
+        check("foo", "foobar", false);
+        check("*", "anything", true);
+        check("*", ".git", true);
+        check("foo-*", "foo-derive", true);
+        check("foo-*", "foo", false);
+        check("*-derive", "foo-derive", true);
+        check("f?o", "foo", true);
+        check("f?o", "fo", false);
+        check("[ab]*", "bar", true);
+        check("[ab]*", "car", false);
+        check("[!ab]*", "car", true);
+        check("[a-c]x", "bx", true);
+        check("[a-c]x", "dx", false);
+        check("[]]", "]", true);
+        check("[x", "[x", true);
+    }
+
+    #[test]
+    fn test_matches_path() {
+        #[track_caller]
+        fn check(pattern: &str, path: &str, expected: bool) {
+            assert_eq!(
+                matches_path(pattern, Path::new(path)),
+                expected,
+                "{pattern} {path}"
+            );
+        }
+
+        check("tests/fixtures", "tests/fixtures/a", true);
+        check("tests/fixtures", "tests/fixtures", true);
+        check("tests/fixtures", "tests", false);
+        check("tests/fixtures/", "tests/fixtures/a", true);
+        check("./tests", "tests/fixtures/a", true);
+        check("crates/*-test", "crates/foo-test", true);
+        check("crates/*-test", "crates/foo", false);
+        check("**/fixtures", "crates/foo/fixtures/bar", true);
+        check("**/fixtures", "fixtures", true);
+        check("**/fixtures", "crates/foo", false);
+    }
+
+    #[test]
+    fn test_expand() {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR")).join("sludge-cicd-test-projects/graph");
+        let expand = |pattern| {
+            expand(&base, pattern)
+                .unwrap()
+                .iter()
+                .map(|path| path.strip_prefix(&base).unwrap().display().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(expand("*/"), ["a", "b", "c", "d", "e"]);
+        assert_eq!(expand("[b-d]"), ["b", "c", "d"]);
+        assert_eq!(expand("./a"), ["a"]);
+        assert_eq!(expand("**/Cargo.toml").len(), 6);
+        assert_eq!(expand("missing/*"), Vec::<String>::new());
+    }
+}
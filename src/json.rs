@@ -0,0 +1,258 @@
+//! A JSON parser, for reading the output of `cargo metadata`.
+
+use std::{char, str};
+
+use crate::Result;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Array(Vec<Json>),
+    /// The members of an object, in the order they appear in the document.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut parser = Parser { src, pos: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos != src.len() {
+            return Err(parser.error("trailing characters after the value"));
+        }
+        Ok(value)
+    }
+
+    /// Looks up a member of an object. Returns `None` if the member doesn't exist, or this isn't
+    /// an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        let Self::Object(members) = self else {
+            return None;
+        };
+        members.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        if let Self::Str(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        if let Self::Array(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> crate::Error {
+        let before = &self.src[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        format!("invalid JSON at line {line}, column {column}: {msg}").into()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<()> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", c as char)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json> {
+        self.skip_whitespace();
+        let rest = &self.src[self.pos..];
+        for (literal, value) in [
+            ("null", Json::Null),
+            ("true", Json::Bool(true)),
+            ("false", Json::Bool(false)),
+        ] {
+            if rest.starts_with(literal) {
+                self.pos += literal.len();
+                return Ok(value);
+            }
+        }
+
+        match self.peek() {
+            Some(b'"') => self.string().map(Json::Str),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut elements = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        elements.push(self.value()?);
+                        if !self.eat(b',') {
+                            break;
+                        }
+                    }
+                    self.expect(b']')?;
+                }
+                Ok(Json::Array(elements))
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        if self.peek() != Some(b'"') {
+                            return Err(self.error("expected a string key"));
+                        }
+                        let key = self.string()?;
+                        self.expect(b':')?;
+                        members.push((key, self.value()?));
+                        if !self.eat(b',') {
+                            break;
+                        }
+                    }
+                    self.expect(b'}')?;
+                }
+                Ok(Json::Object(members))
+            }
+            Some(b'-' | b'0'..=b'9') => {
+                let len = rest
+                    .find(|c: char| !matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+                    .unwrap_or(rest.len());
+                let number = rest[..len]
+                    .parse()
+                    .map_err(|_| self.error("invalid number"))?;
+                self.pos += len;
+                Ok(Json::Number(number))
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    /// Parses a string, starting at its opening `"`.
+    fn string(&mut self) -> Result<String> {
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let rest = &self.src[self.pos..];
+            let Some(end) = rest.find(['"', '\\']) else {
+                return Err(self.error("unterminated string"));
+            };
+            out += &rest[..end];
+            self.pos += end + 1;
+            if rest.as_bytes()[end] == b'"' {
+                return Ok(out);
+            }
+
+            let escaped = match self.peek() {
+                Some(b'"') => '"',
+                Some(b'\\') => '\\',
+                Some(b'/') => '/',
+                Some(b'b') => '\u{8}',
+                Some(b'f') => '\u{c}',
+                Some(b'n') => '\n',
+                Some(b'r') => '\r',
+                Some(b't') => '\t',
+                Some(b'u') => {
+                    let first = self.hex4()?;
+                    let code = if (0xD800..0xDC00).contains(&first)
+                        && self.src[self.pos + 1..].starts_with("\\u")
+                    {
+                        self.pos += 2;
+                        let second = self.hex4()?;
+                        0x10000 + ((first - 0xD800) << 10) + (second.wrapping_sub(0xDC00) & 0x3FF)
+                    } else {
+                        first
+                    };
+                    char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                }
+                _ => return Err(self.error("invalid escape sequence")),
+            };
+            self.pos += 1;
+            out.push(escaped);
+        }
+    }
+
+    /// Parses the 4 hex digits after a `\u`, leaving `pos` at the last digit.
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self
+            .src
+            .get(self.pos + 1..self.pos + 5)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("invalid `\\u` escape"))?;
+        self.pos += 4;
+        Ok(u32::from_str_radix(digits, 16).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let json =
+            Json::parse(r#" {"name": "a\"bé😀", "list": [1, -2.5e3, true, null, []], "o": {}} "#)
+                .unwrap();
+        assert_eq!(json.get("name").unwrap().as_str(), Some("a\"bé😀"));
+        assert_eq!(
+            json.get("list").unwrap(),
+            &Json::Array(vec![
+                Json::Number(1.0),
+                Json::Number(-2500.0),
+                Json::Bool(true),
+                Json::Null,
+                Json::Array(Vec::new()),
+            ])
+        );
+        assert_eq!(json.get("o"), Some(&Json::Object(Vec::new())));
+        assert_eq!(json.get("missing"), None);
+
+        for (src, error) in [
+            ("", "invalid JSON at line 1, column 1: expected a value"),
+            ("[1,]", "invalid JSON at line 1, column 4: expected a value"),
+            (
+                "{\n\"a\" 1}",
+                "invalid JSON at line 2, column 5: expected `:`",
+            ),
+            (
+                "\"abc",
+                "invalid JSON at line 1, column 2: unterminated string",
+            ),
+            (
+                "[] []",
+                "invalid JSON at line 1, column 4: trailing characters after the value",
+            ),
+        ] {
+            assert_eq!(Json::parse(src).unwrap_err().to_string(), error, "{src:?}");
+        }
+    }
+}
@@ -1,23 +1,36 @@
 #[cfg(test)]
 #[macro_use]
 mod tests;
+mod cargo_config;
+mod changelog;
+mod commits;
+mod diagnostic;
+mod fragments;
+mod glob;
+mod json;
 mod markdown;
+mod semver;
 mod toml;
 mod utils;
 
 use std::{
+    collections::{BTreeMap, BTreeSet},
     env::{self, VarError},
     fmt::{self, Write as _},
     fs::{self, File},
     io::{self, stdout, Write as _},
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::{self, Command, ExitStatus, Stdio},
     str,
     time::{Duration, Instant},
 };
 
+use cargo_config::CargoConfig;
+use diagnostic::Diagnostic;
+use json::Json;
 use markdown::Markdown;
-use toml::{Toml, Value};
+use semver::Version;
+use toml::{Table, Toml, Value};
 
 type Error = Box<dyn std::error::Error>;
 type Result<T> = std::result::Result<T, Error>;
@@ -30,6 +43,9 @@ macro_rules! bail {
 
 fn main() {
     if let Err(err) = try_main() {
+        if let Some(diag) = err.downcast_ref::<Diagnostic>() {
+            eprintln!("{}", diag.annotation());
+        }
         eprintln!("{}", err);
         process::exit(1);
     }
@@ -37,7 +53,13 @@ fn main() {
 
 fn try_main() -> Result<()> {
     let cwd = env::current_dir()?;
-    let args = env::args().skip(1).collect::<Vec<_>>().join(" ");
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if let [command, subcommand @ ..] = &*args {
+        if command == "changelog" {
+            return changelog_command(cwd, subcommand);
+        }
+    }
+    let args = args.join(" ");
     let crates_io_token = match env::var("CRATES_IO_TOKEN") {
         Ok(s) if s.is_empty() => None,
         Ok(s) => Some(s),
@@ -50,10 +72,35 @@ fn try_main() -> Result<()> {
         Err(env::VarError::NotPresent) => None,
         Err(e @ env::VarError::NotUnicode(_)) => return Err(e.into()),
     };
+    // Tokens for registries other than crates.io use the same variables as Cargo.
+    let mut registry_tokens = BTreeMap::new();
+    for (key, value) in env::vars() {
+        if let Some(name) = registry_token_name(&key) {
+            if !value.is_empty() {
+                registry_tokens.insert(name.to_string(), value);
+            }
+        }
+    }
+    let mut attachments = BTreeMap::new();
+    for (key, value) in env::vars() {
+        if let Some(name) = key.strip_prefix("ATTACHMENTS_") {
+            attachments.insert(name.to_string(), value);
+        }
+    }
     let check_only = env::var_os("CICD_CHECK_ONLY").is_some();
     let skip_docs = env::var_os("CICD_SKIP_DOCS").is_some();
     let sudo = env::var_os("CICD_SUDO").is_some();
     let no_publish = env::var_os("CICD_NO_PUBLISH").is_some();
+    let commit_notes = env::var_os("CICD_COMMIT_NOTES").is_some();
+    let workspaces = match env::var("CICD_WORKSPACES") {
+        Ok(s) => s
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .collect(),
+        Err(VarError::NotPresent) => Vec::new(),
+        Err(e @ VarError::NotUnicode(_)) => return Err(e.into()),
+    };
     let commit = env::var("GITHUB_SHA")?;
     let cargo_doc_flags = match env::var("CICD_CARGO_DOC_FLAGS") {
         Ok(s) => s,
@@ -66,15 +113,125 @@ fn try_main() -> Result<()> {
         args,
         crates_io_token,
         github_token,
+        registry_tokens,
+        attachments,
         commit,
         cargo_doc_flags,
         check_only,
         skip_docs,
         sudo,
         no_publish,
+        commit_notes,
+        workspaces,
         mock_output: None,
     };
-    Pipeline::new(params)?.run()
+    run_workspaces(params)
+}
+
+/// Runs the pipeline for every workspace in the repository, one after the other.
+///
+/// With several workspaces, a summary of what was published is printed at the end (also if one
+/// of them fails).
+fn run_workspaces(mut params: Params) -> Result<()> {
+    if !params.is_mock_test() {
+        // This is useless to mock and clutters up the test data.
+        print_info()?;
+    }
+
+    if params.workspaces.is_empty() {
+        Pipeline::new(&mut params, None)?.run()?;
+        return Ok(());
+    }
+
+    let roots = params.workspaces.clone();
+
+    // Each workspace only attaches files to its own packages, so make sure that none are missed.
+    for name in params.attachments.keys() {
+        let mut found = false;
+        for root in &roots {
+            let packages = Workspace::get(params.cwd.join(root))?.packages()?;
+            if packages.iter().any(|package| package.name == *name) {
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            bail!("`ATTACHMENTS_{name}` is set, but package {name} doesn't exist in any workspace");
+        }
+    }
+
+    let mut summary = Vec::new();
+    let mut error = None;
+    for root in &roots {
+        match Pipeline::new(&mut params, Some(root)).and_then(Pipeline::run) {
+            Ok(published) => summary.push((root, published)),
+            Err(e) => {
+                error = Some((root, e));
+                break;
+            }
+        }
+    }
+
+    let _s = Section::new("SUMMARY");
+    for (root, published) in summary {
+        if published.is_empty() {
+            println!("{}: nothing published", root.display());
+        } else {
+            println!("{}: published {published:?}", root.display());
+        }
+    }
+    match error {
+        Some((root, e)) => {
+            println!("{}: failed", root.display());
+            Err(e)
+        }
+        None => Ok(()),
+    }
+}
+
+fn print_info() -> Result<()> {
+    let _s = Section::new("INFO");
+    println!(concat!(
+        env!("CARGO_PKG_NAME"),
+        " version ",
+        env!("CARGO_PKG_VERSION")
+    ));
+    print!("PATH=");
+    if let Some(path) = env::var_os("PATH") {
+        stdout().write_all(path.as_encoded_bytes())?;
+    }
+    println!();
+    shell("rustup toolchain list")?;
+    shell("rustc -Vv")?;
+    shell("git --version")?;
+    Ok(())
+}
+
+/// Runs `sludge-cicd changelog <subcommand>`.
+fn changelog_command(cwd: PathBuf, args: &[String]) -> Result<()> {
+    let args = args.iter().map(|s| &**s).collect::<Vec<_>>();
+    match *args {
+        ["lint"] => {
+            let workspace = Workspace::get(cwd)?;
+            let packages = workspace.packages()?;
+            lint_changelogs(&workspace, &packages)?;
+            println!("no problems found");
+            Ok(())
+        }
+        ["release", ref package @ ..] if package.len() <= 1 => {
+            let workspace = Workspace::get(cwd)?;
+            let packages = workspace.packages()?;
+            release_changelogs(&workspace, &packages, package.first().copied())
+        }
+        ["assemble", ref package @ ..] if package.len() <= 1 => {
+            let workspace = Workspace::get(cwd)?;
+            let packages = workspace.packages()?;
+            assemble_fragments(&workspace, &packages, package.first().copied())
+        }
+        _ => bail!(
+            "usage: sludge-cicd changelog lint | sludge-cicd changelog release [<package>] | sludge-cicd changelog assemble [<package>]"
+        ),
+    }
 }
 
 struct Params {
@@ -82,17 +239,31 @@ struct Params {
     args: String,
     crates_io_token: Option<String>,
     github_token: Option<String>,
+    /// Values of `CARGO_REGISTRIES_<NAME>_TOKEN`, keyed by `<NAME>`.
+    registry_tokens: BTreeMap<String, String>,
+    /// Values of `ATTACHMENTS_<pkgname>`, keyed by `<pkgname>`.
+    attachments: BTreeMap<String, String>,
     commit: String,
     cargo_doc_flags: String,
     check_only: bool,
     skip_docs: bool,
     sudo: bool,
     no_publish: bool,
+    /// Generate release notes from the git history for packages without changelog entries.
+    commit_notes: bool,
+    /// The roots of the workspaces in the repository, relative to `cwd`. If empty, `cwd` is the
+    /// only workspace.
+    workspaces: Vec<PathBuf>,
     mock_output: Option<Vec<(&'static str, String)>>,
 }
 
-struct Pipeline {
-    params: Params,
+/// The pipeline for one workspace.
+struct Pipeline<'a> {
+    params: &'a mut Params,
+    /// The workspace root relative to `params.cwd`, if the repository has several workspaces.
+    root: Option<PathBuf>,
+    /// The workspace root directory.
+    dir: PathBuf,
     /// List of publishable packages in workspace.
     ///
     /// "Publishable" means:
@@ -103,86 +274,110 @@ struct Pipeline {
     /// A package does not need to have a version that hasn't been published yet to be in this
     /// list.
     packages: Vec<Package>,
+    cargo_config: CargoConfig,
 }
 
-impl Pipeline {
-    fn new(params: Params) -> Result<Self> {
+impl<'a> Pipeline<'a> {
+    fn new(params: &'a mut Params, root: Option<&Path>) -> Result<Self> {
         let _s = Section::new("INIT");
 
-        let workspace = Workspace::get(params.cwd.clone())?;
-        let mut packages = workspace.find_packages()?;
-
-        extract_release_notes(&mut packages, &workspace)?;
+        let dir = match root {
+            Some(root) => {
+                println!("workspace: {}", root.display());
+                params.cwd.join(root)
+            }
+            None => params.cwd.clone(),
+        };
+        let workspace = Workspace::get(dir.clone())?;
+        let mut packages = workspace.packages()?;
+
+        lint_changelogs(&workspace, &packages)?;
+        extract_release_notes(&mut packages, &workspace, &params.cwd, &params.commit)?;
+        for package in &mut packages {
+            if let Some(notes) = &package.release_notes {
+                let notes = changelog::sanitize(notes, &workspace.excluded_blocks);
+                package.release_notes = Some(notes);
+            }
+        }
 
         println!("publishable packages in workspace: {:?}", packages);
 
-        Ok(Self { params, packages })
+        let cargo_config = CargoConfig::load(&dir)?;
+        // Report where packages go, unless everything goes to crates.io.
+        let routes = packages
+            .iter()
+            .map(|package| (package, registries(package, &cargo_config)))
+            .collect::<Vec<_>>();
+        if routes
+            .iter()
+            .any(|(_, registries)| *registries != [CRATES_IO])
+        {
+            for (package, registries) in routes {
+                let registries = registries
+                    .iter()
+                    .map(|registry| format!("`{registry}`"))
+                    .collect::<Vec<_>>();
+                println!("{package} is published to {}", registries.join(", "));
+            }
+        }
+        for command in ["check", "doc", "publish", "test"] {
+            if cargo_config.aliases.contains_key(command) {
+                eprintln!(
+                    "::warning::Cargo config defines an alias for `cargo {command}`, which Cargo ignores in favor of the built-in command"
+                );
+            }
+        }
+
+        Ok(Self {
+            params,
+            root: root.map(Path::to_path_buf),
+            dir,
+            packages,
+            cargo_config,
+        })
     }
 
-    fn run(mut self) -> Result<()> {
-        self.step_info()?;
+    /// Runs the pipeline, and returns the packages that were published.
+    fn run(mut self) -> Result<Vec<Package>> {
         self.step_test()?;
         self.step_gitcheck()?;
         self.step_manifest_check()?;
-        self.step_publish()?;
-        Ok(())
+        self.step_publish()
     }
 
-    fn step_info(&mut self) -> Result<()> {
-        if self.params.is_mock_test() {
-            // This is useless to mock and clutters up the test data.
-            return Ok(());
-        }
-
-        let _s = Section::new("INFO");
-        println!(concat!(
-            env!("CARGO_PKG_NAME"),
-            " version ",
-            env!("CARGO_PKG_VERSION")
-        ));
-        print!("PATH=");
-        if let Some(path) = env::var_os("PATH") {
-            stdout().write_all(path.as_encoded_bytes())?;
-        }
-        println!();
-        shell("rustup toolchain list")?;
-        shell("rustc -Vv")?;
-        shell("git --version")?;
-        Ok(())
+    /// Runs a Cargo command in the workspace root.
+    fn cargo(&self, cmd: &str, sudo: bool) -> Result<()> {
+        shell_ex(cmd, "", sudo, self.root.as_deref())
     }
 
     fn step_test(&mut self) -> Result<()> {
         let args = &self.params.args;
-        let cargo_toml = self.params.cwd.join("Cargo.toml");
+        let cargo_toml = self.dir.join("Cargo.toml");
         assert!(
             cargo_toml.exists(),
             "Cargo.toml not found, cwd: {}",
-            self.params.cwd.display()
+            self.dir.display()
         );
 
         if self.params.check_only {
             let _s = Section::new("CHECK");
-            shell(&format!("cargo check --workspace {args}"))?;
+            self.cargo(&format!("cargo check --workspace {args}"), false)?;
         } else {
             let _s = Section::new("BUILD");
-            shell(&format!("cargo test --workspace --no-run {args}"))?;
+            self.cargo(&format!("cargo test --workspace --no-run {args}"), false)?;
         }
 
         if !self.params.skip_docs {
             let _s = Section::new("BUILD_DOCS");
-            shell(&format!(
-                "cargo doc --workspace {}",
-                self.params.cargo_doc_flags
-            ))?;
+            self.cargo(
+                &format!("cargo doc --workspace {}", self.params.cargo_doc_flags),
+                false,
+            )?;
         }
 
         if !self.params.check_only {
             let _s = Section::new("TEST");
-            shell_ex(
-                &format!("cargo test --workspace {args}"),
-                "",
-                self.params.sudo,
-            )?;
+            self.cargo(&format!("cargo test --workspace {args}"), self.params.sudo)?;
         }
 
         Ok(())
@@ -220,60 +415,85 @@ impl Pipeline {
             return Ok(());
         }
 
-        for Package { name, manifest, .. } in &self.packages {
-            let toml = Toml(manifest);
-            if !toml.get_field("description").is_ok()
-                && !toml.get_field("description.workspace").is_ok()
-            {
-                bail!("package `{name}` is missing a description field");
+        for package in &self.packages {
+            let name = &package.name;
+            let toml = Toml::parse_file(&package.manifest, &package.manifest_path)?;
+            if !package.fields.contains_key("description") {
+                let msg = format!("package `{name}` is missing a description field");
+                return Err(toml.error(&["package", "description"], msg).into());
             }
 
-            if !toml.get_field("license").is_ok()
-                && !toml.get_field("license-file").is_ok()
-                && !toml.get_field("license.workspace").is_ok()
-                && !toml.get_field("license-file.workspace").is_ok()
+            if !package.fields.contains_key("license")
+                && !package.fields.contains_key("license-file")
             {
-                bail!("package `{name}` is missing a license or license-file field");
+                let msg = format!("package `{name}` is missing a license or license-file field");
+                return Err(toml.error(&["package", "license"], msg).into());
+            }
+
+            check_package_metadata(package, &toml)?;
+
+            for dep in &package.dependencies {
+                if dep.path.is_some() && dep.version.is_none() && !dep.dev {
+                    let msg = format!(
+                        "package `{name}` depends on `{}` by path only, which can't be published without a `version`",
+                        dep.package
+                    );
+                    let decl = dep.decl.iter().map(|s| &**s).collect::<Vec<_>>();
+                    return Err(toml.error(&decl, msg).into());
+                }
             }
         }
 
         if self.packages.is_empty() {
             // This can happen if no package specifies a version (because nothing is intended to be
             // published), so we don't do this check if `CICD_NO_PUBLISH` is set.
-            bail!(
-                "no publishable packages found in '{}'",
-                self.params.cwd.display()
-            );
+            bail!("no publishable packages found in '{}'", self.dir.display());
         }
 
         Ok(())
     }
 
-    fn step_publish(&mut self) -> Result<()> {
+    fn step_publish(&mut self) -> Result<Vec<Package>> {
         let current_branch = self.params.shell_output("git branch --show-current")?;
         let _s = Section::new("PUBLISH");
 
         let tags_string = self.params.shell_output("git tag --list")?;
-        let tags = tags_string.split_whitespace().collect::<Vec<_>>();
+        let mut tags = tags_string.split_whitespace().collect::<Vec<_>>();
+        if self.root.is_some() {
+            // With several workspaces, a `vX.Y.Z` tag can't tell which one was released, so only
+            // package tags are used.
+            tags.retain(|tag| tag.strip_prefix('v').and_then(Version::parse).is_none());
+        }
         println!("existing git tags: {tags:?}");
 
-        // We do this step here (instead of during `INIT`) so that the attachments can be generated
-        // by the build/test step, and before checking whether we have a crates.io token so that the
-        // attachments are always validated to exist.
-        extract_release_attachments(&mut self.packages)?;
-
         let same_version = self
             .packages
             .iter()
             .all(|pkg| pkg.version == self.packages[0].version);
         let has_package_specific_changelog =
             self.packages.iter().any(|pkg| pkg.changelog_path.is_some());
-        let separate_tags = has_package_specific_changelog
+        let separate_tags = self.root.is_some()
+            || has_package_specific_changelog
             || !same_version
             || tags
                 .iter()
                 .any(|tag| tag.ends_with(&self.packages[0].version));
 
+        if self.params.commit_notes {
+            generate_release_notes(self.params, &mut self.packages, &tags)?;
+        }
+
+        // We do this step here (instead of during `INIT`) so that the attachments can be generated
+        // by the build/test step, and before checking whether we have a crates.io token so that the
+        // attachments are always validated to exist. Release notes generated from commits have to
+        // exist by now, since attachments need a release.
+        extract_release_attachments(
+            &mut self.packages,
+            &self.params.attachments,
+            &self.cargo_config,
+            self.root.is_some(),
+        )?;
+
         let to_publish = self
             .packages
             .iter()
@@ -284,7 +504,7 @@ impl Pipeline {
             .collect::<Vec<_>>();
         if to_publish.is_empty() {
             println!("no packages need publishing, done");
-            return Ok(());
+            return Ok(Vec::new());
         }
 
         println!(
@@ -295,26 +515,72 @@ impl Pipeline {
             to_publish
         );
 
-        let Some(token) = self.params.crates_io_token.clone() else {
-            println!("no `CRATES_IO_TOKEN` set, skipping autopublish step");
-            return Ok(());
-        };
+        // Packages are published to the registries in their `publish` list, or the default
+        // registry (which `registry.default` changes), so we need a token for each of those.
+        let mut tokens = BTreeMap::new();
+        for package in &to_publish {
+            for registry in registries(package, &self.cargo_config) {
+                if tokens.contains_key(registry) {
+                    continue;
+                }
+                let token = if registry == CRATES_IO {
+                    let Some(token) = self.params.crates_io_token.clone() else {
+                        println!("no `CRATES_IO_TOKEN` set, skipping autopublish step");
+                        return Ok(Vec::new());
+                    };
+                    token
+                } else {
+                    if !self.cargo_config.registries.contains_key(registry) {
+                        match package.registries {
+                            Some(_) => bail!("package `{}` is published to `{registry}`, but there is no `registries.{registry}` in the Cargo config", package.name),
+                            None => bail!("`registry.default` is set to `{registry}`, but there is no `registries.{registry}` in the Cargo config"),
+                        }
+                    }
+                    let var = registry.to_ascii_uppercase().replace('-', "_");
+                    let Some(token) = self.params.registry_tokens.get(&var).cloned() else {
+                        println!(
+                            "no `CARGO_REGISTRIES_{var}_TOKEN` set, skipping autopublish step"
+                        );
+                        return Ok(Vec::new());
+                    };
+                    token
+                };
+                tokens.insert(registry, token);
+            }
+        }
+        // Only crates.io doesn't need `--registry`, if it's the default.
+        let default_registry = self.cargo_config.default_registry.as_deref();
+        let needs_flag =
+            |registry| registry != CRATES_IO || default_registry.unwrap_or(CRATES_IO) != CRATES_IO;
         if &current_branch != "main" {
             println!("not on `main` branch, skipping autopublish step");
-            return Ok(());
+            return Ok(Vec::new());
         }
 
-        for Package { name, version, .. } in &to_publish {
+        for package in &to_publish {
             // If there is neither a `$package-v$version` tag, nor a `v$version` tag, the package
             // should be published.
             // If all publishable packages are at the same version, and no tag that ends in that
             // version exists, we'll use a single collective `v$version` tag for all packages.
+            let Package { name, version, .. } = package;
+            for registry in registries(package, &self.cargo_config) {
+                if registry == CRATES_IO {
+                    println!("publishing {name}@{version}");
+                } else {
+                    println!("publishing {name}@{version} to registry `{registry}`");
+                }
+                let registry_flag = match needs_flag(registry) {
+                    true => format!(" --registry {registry}"),
+                    false => String::new(),
+                };
+                let token = &tokens[registry];
 
-            // NB: we use `--no-verify` because we've already tested the package earlier.
-            println!("publishing {name}@{version}");
-            shell(&format!(
-                "cargo publish --no-verify -p {name} --token {token}"
-            ))?;
+                // NB: we use `--no-verify` because we've already tested the package earlier.
+                self.cargo(
+                    &format!("cargo publish --no-verify -p {name}{registry_flag} --token {token}"),
+                    false,
+                )?;
+            }
         }
 
         if separate_tags {
@@ -359,29 +625,33 @@ impl Pipeline {
             // Shared tag -> Create merged release notes from all packages.
             // If multiple packages have the same relnotes, deduplicate them (likely from the
             // workspace-level CHANGELOG.md).
-            let mut entries = Vec::new();
-            let mut prev_notes = None;
+            let mut entries: Vec<(&str, Vec<&Package>)> = Vec::new();
             for package in &to_publish {
                 if let Some(notes) = &package.release_notes {
-                    if Some(&**notes) == prev_notes {
-                        continue;
+                    match entries.iter_mut().find(|(n, _)| n == notes) {
+                        Some((_, packages)) => packages.push(package),
+                        None => entries.push((notes, vec![package])),
                     }
-                    prev_notes = Some(&**notes);
-                    entries.push(package);
                 }
             }
 
             let mut relnotes = String::new();
-            for package in &entries {
-                if let Some(notes) = &package.release_notes {
-                    if !relnotes.is_empty() {
-                        relnotes += "\n";
-                    }
+            for (notes, packages) in &entries {
+                if !relnotes.is_empty() {
+                    relnotes += "\n";
+                }
 
-                    if entries.len() > 1 {
-                        writeln!(relnotes, "# {} {}", package.name, package.version).ok();
-                        writeln!(relnotes).ok();
-                    }
+                if entries.len() > 1 {
+                    let title = packages
+                        .iter()
+                        .map(|pkg| format!("{} {}", pkg.name, pkg.version))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    writeln!(relnotes, "# {title}").ok();
+                    writeln!(relnotes).ok();
+                    // Headings in the notes have to be nested below the package title.
+                    writeln!(relnotes, "{}", Markdown(notes).relevel_headings(2)).ok();
+                } else {
                     writeln!(relnotes, "{notes}").ok();
                 }
             }
@@ -393,7 +663,7 @@ impl Pipeline {
                     &relnotes,
                 )?;
 
-                let files = entries
+                let files = to_publish
                     .iter()
                     .flat_map(|pkg| pkg.release_attachments.iter())
                     .map(|p| p.display().to_string())
@@ -407,19 +677,297 @@ impl Pipeline {
             }
         }
 
-        Ok(())
+        Ok(to_publish.into_iter().cloned().collect())
     }
 }
 
-fn extract_release_notes(packages: &mut [Package], workspace: &Workspace) -> Result<()> {
-    for package in packages {
-        let Some(mut changelog_path) = package
-            .changelog_path
+/// The name Cargo uses for crates.io in `publish` lists and `registry.default`.
+const CRATES_IO: &str = "crates-io";
+
+/// Returns the registries `package` is published to: those in its `publish` list, or else the
+/// default registry.
+fn registries<'a>(package: &'a Package, cargo_config: &'a CargoConfig) -> Vec<&'a str> {
+    match &package.registries {
+        Some(registries) => registries.iter().map(|registry| &**registry).collect(),
+        None => vec![cargo_config
+            .default_registry
             .as_deref()
-            .or(workspace.changelog.as_deref())
+            .unwrap_or(CRATES_IO)],
+    }
+}
+
+/// Checks the structure of the workspace changelog and all package changelogs.
+///
+/// All problems are printed as GitHub annotations before an error is returned.
+fn lint_changelogs(workspace: &Workspace, packages: &[Package]) -> Result<()> {
+    let paths = workspace.changelog.iter().chain(
+        packages
+            .iter()
+            .filter_map(|pkg| pkg.changelog_path.as_ref()),
+    );
+    let mut problems = 0;
+    for path in paths {
+        let changelog = fs::read_to_string(path)?;
+        for diag in changelog::lint(&changelog, path) {
+            eprintln!("{}", diag.annotation());
+            eprintln!("{diag}");
+            problems += 1;
+        }
+    }
+    if problems != 0 {
+        bail!(
+            "found {problems} problem{} in changelogs",
+            if problems == 1 { "" } else { "s" }
+        );
+    }
+    Ok(())
+}
+
+/// Returns the changelog that contains the release notes for `package`.
+///
+/// That's either the package's own changelog, or the workspace changelog.
+fn changelog_path<'a>(package: &'a Package, workspace: &'a Workspace) -> Option<&'a Path> {
+    package
+        .changelog_path
+        .as_deref()
+        .or(workspace.changelog.as_deref())
+}
+
+/// Returns the changelog fragment directory for `package`.
+///
+/// That's either the package's own fragment directory, or the workspace's.
+fn fragments_path<'a>(package: &'a Package, workspace: &'a Workspace) -> Option<&'a Path> {
+    package
+        .fragments_path
+        .as_deref()
+        .or(workspace.fragments.as_deref())
+}
+
+/// Promotes the `Unreleased` sections of the changelogs to sections for the current package
+/// versions.
+///
+/// If `only` is given, only the changelog of that package is changed.
+fn release_changelogs(
+    workspace: &Workspace,
+    packages: &[Package],
+    only: Option<&str>,
+) -> Result<()> {
+    if let Some(name) = only {
+        if !packages.iter().any(|pkg| pkg.name == name) {
+            bail!("package `{name}` does not exist in the workspace");
+        }
+    }
+
+    let mut changelogs = BTreeMap::<&Path, Vec<&Package>>::new();
+    for package in packages {
+        if let Some(path) = changelog_path(package, workspace) {
+            changelogs.entry(path).or_default().push(package);
+        }
+    }
+
+    let date = changelog::today();
+    let mut promoted = 0;
+    for (path, sharing) in changelogs {
+        let targets = match only {
+            Some(name) => sharing
+                .iter()
+                .copied()
+                .filter(|pkg| pkg.name == name)
+                .collect::<Vec<_>>(),
+            None => sharing.clone(),
+        };
+        let Some(first) = targets.first() else {
+            continue;
+        };
+        let changelog = fs::read_to_string(path)?;
+        if only.is_none()
+            && !Markdown(&changelog)
+                .headings()
+                .iter()
+                .any(|heading| changelog::is_unreleased(heading.title))
+        {
+            continue;
+        }
+
+        // A changelog shared by packages with different versions needs a section per package.
+        let same_version = sharing.iter().all(|pkg| pkg.version == first.version);
+        if !same_version && targets.len() > 1 {
+            bail!(
+                "changelog '{}' is shared by packages with different versions; pass the name of the package to release",
+                path.display()
+            );
+        }
+        let name = (!same_version).then_some(&*first.name);
+        let Some(version) = Version::parse(&first.version) else {
+            bail!(
+                "version `{}` of package `{}` is not a valid semantic version",
+                first.version,
+                first.name
+            );
+        };
+
+        let changelog = changelog::promote_unreleased(&changelog, name, &version, date)
+            .map_err(|e| format!("cannot update '{}': {e}", path.display()))?;
+        fs::write(path, changelog)?;
+        println!("added section for {version} to '{}'", path.display());
+        promoted += 1;
+    }
+
+    if promoted == 0 {
+        bail!("no changelog has an `Unreleased` section");
+    }
+    Ok(())
+}
+
+/// Writes the changelog fragments into a new section of the changelog for the current package
+/// version, and deletes them.
+///
+/// If `only` is given, only the fragments of that package are assembled.
+fn assemble_fragments(
+    workspace: &Workspace,
+    packages: &[Package],
+    only: Option<&str>,
+) -> Result<()> {
+    if let Some(name) = only {
+        if !packages.iter().any(|pkg| pkg.name == name) {
+            bail!("package `{name}` does not exist in the workspace");
+        }
+    }
+
+    let mut dirs = BTreeMap::<&Path, Vec<&Package>>::new();
+    for package in packages {
+        if let Some(dir) = fragments_path(package, workspace) {
+            dirs.entry(dir).or_default().push(package);
+        }
+    }
+
+    let date = changelog::today();
+    let mut assembled = 0;
+    for (dir, sharing) in dirs {
+        let Some(first) = sharing
+            .iter()
+            .find(|pkg| only.is_none_or(|name| pkg.name == name))
         else {
             continue;
         };
+        let fragments = fragments::read(dir)?;
+        if fragments.is_empty() {
+            continue;
+        }
+
+        // Fragments shared by packages with different versions can't be attributed to one of them.
+        if !sharing.iter().all(|pkg| pkg.version == first.version) {
+            bail!(
+                "fragment directory '{}' is shared by packages with different versions",
+                dir.display()
+            );
+        }
+        let Some(version) = Version::parse(&first.version) else {
+            bail!(
+                "version `{}` of package `{}` is not a valid semantic version",
+                first.version,
+                first.name
+            );
+        };
+
+        // Without a changelog, one is created next to the manifest the fragments belong to.
+        let changelog_location = |package: &Package| -> Result<PathBuf> {
+            Ok(match changelog_path(package, workspace) {
+                Some(path) => path.to_path_buf(),
+                None if package.fragments_path.is_some() => {
+                    let toml = Toml::parse_file(&package.manifest, &package.manifest_path)?;
+                    let dir = package.manifest_path.parent().unwrap_or(Path::new(""));
+                    workspace.package_changelog_location(&toml, dir, &package.name)?
+                }
+                None => workspace.changelog_location.clone(),
+            })
+        };
+        let path = changelog_location(first)?;
+
+        // The workspace's fragments belong to every package without its own fragment directory,
+        // so they can't be moved into the changelog of just one of them.
+        for package in &sharing {
+            if changelog_location(package)? != path {
+                bail!(
+                    "fragment directory '{}' is shared by `{}` and `{}`, which have different changelogs (packages with their own changelog need their own fragment directory)",
+                    dir.display(),
+                    first.name,
+                    package.name,
+                );
+            }
+        }
+        let changelog = match fs::read_to_string(&path) {
+            Ok(changelog) => changelog,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => "# Changelog\n".to_string(),
+            Err(e) => return Err(e.into()),
+        };
+        // If other packages with the same version share the changelog, the section needs the
+        // package name to tell them apart.
+        let name = packages
+            .iter()
+            .any(|pkg| {
+                changelog_path(pkg, workspace) == Some(&*path)
+                    && pkg.version == first.version
+                    && !sharing.iter().any(|other| other.name == pkg.name)
+            })
+            .then_some(&*first.name);
+
+        let notes = fragments::assemble(&fragments);
+        let changelog = changelog::add_release(&changelog, name, &version, date, &notes)
+            .map_err(|e| format!("cannot update '{}': {e}", path.display()))?;
+        fs::write(&path, changelog)?;
+        for fragment in &fragments {
+            fs::remove_file(&fragment.path)?;
+        }
+        println!(
+            "added {} fragment{} to the section for {version} in '{}'",
+            fragments.len(),
+            if fragments.len() == 1 { "" } else { "s" },
+            path.display()
+        );
+        assembled += 1;
+    }
+
+    if assembled == 0 {
+        bail!("no changelog fragments found");
+    }
+    Ok(())
+}
+
+fn extract_release_notes(
+    packages: &mut [Package],
+    workspace: &Workspace,
+    repo_root: &Path,
+    commit: &str,
+) -> Result<()> {
+    let names = packages
+        .iter()
+        .map(|pkg| pkg.name.clone())
+        .collect::<Vec<_>>();
+    let names = names.iter().map(|name| &**name).collect::<Vec<_>>();
+    for package in packages {
+        // Fragments are used if the changelog doesn't have an entry for the version yet.
+        let fragments = match fragments_path(package, workspace) {
+            Some(dir) => fragments::read(dir)?,
+            None => Vec::new(),
+        };
+        let Some(mut changelog_path) = changelog_path(package, workspace) else {
+            if !fragments.is_empty() {
+                package.release_notes = Some(fragments::assemble(&fragments));
+            }
+            continue;
+        };
+        // Relative links in the changelog are relative to its directory in the repository.
+        let changelog_dir = changelog_path
+            .parent()
+            .and_then(|dir| dir.strip_prefix(repo_root).ok())
+            .map(|dir| {
+                dir.components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .unwrap_or_default();
 
         // There is a package-specific changelog. It has to contain a single heading for the
         // version we're about to release.
@@ -430,10 +978,23 @@ fn extract_release_notes(packages: &mut [Package], workspace: &Workspace) -> Res
             changelog_path = p;
         }
 
+        let Some(version) = Version::parse(&package.version) else {
+            bail!(
+                "version `{}` of package `{}` is not a valid semantic version",
+                package.version,
+                package.name
+            );
+        };
+
         let mut entries_matching_version = Vec::new();
         for level in 1..=3 {
             for (title, contents) in Markdown(&changelog).sections(level) {
-                if title.contains(&*package.version) {
+                // An `[Unreleased]` section (from the "Keep a Changelog" format) may already
+                // mention the version that is going to be released, but isn't done yet.
+                if changelog::is_unreleased(title) {
+                    continue;
+                }
+                if Version::find_all(title).contains(&version) {
                     entries_matching_version.push((title, contents.0));
                 }
             }
@@ -443,12 +1004,16 @@ fn extract_release_notes(packages: &mut [Package], workspace: &Workspace) -> Res
         }
 
         let entry = match *entries_matching_version {
+            [] if !fragments.is_empty() => {
+                package.release_notes = Some(fragments::assemble(&fragments));
+                continue;
+            }
             [] => bail!(
                 "changelog at '{}' does not contain an entry for {package}",
                 changelog_path.display()
             ),
             [(_, contents)] => contents,
-            [ref multiple @ ..] => {
+            ref multiple => {
                 let mut entry_containing_name = None;
                 for (title, contents) in multiple {
                     if title.to_ascii_lowercase().contains(&package.name) {
@@ -470,27 +1035,109 @@ fn extract_release_notes(packages: &mut [Package], workspace: &Workspace) -> Res
                 }
             }
         };
+        // A section shared by multiple packages can have a sub-section for each of them.
+        let Some(entry) = changelog::package_notes(entry, &package.name, &names) else {
+            bail!(
+                "changelog '{}' is missing an entry for {package}",
+                changelog_path.display()
+            );
+        };
 
-        package.release_notes = Some(entry.to_string());
+        // Changelogs often put link reference definitions at the end, which would be missing from
+        // the release notes.
+        let definitions = Markdown(&changelog).link_definitions();
+        let mut notes = Markdown(&entry).resolve_reference_links(&definitions);
+        // The release notes are displayed outside of the repository, where relative links don't
+        // work.
+        if let Some(repository) = package.fields.get("repository").and_then(Value::as_str) {
+            notes = changelog::absolutize_links(&notes, repository, commit, &changelog_dir);
+        }
+        package.release_notes = Some(notes.trim().to_string());
     }
 
     Ok(())
 }
 
-fn extract_release_attachments(packages: &mut [Package]) -> Result<()> {
-    if cfg!(test) {
-        // The variables set in ci.yaml won't work for the test projects.
-        return Ok(());
-    }
-
-    for (k, v) in env::vars() {
-        let Some(pkg) = k.strip_prefix("ATTACHMENTS_") else {
+/// Generates release notes from the Conventional Commits since the previous release, for packages
+/// that are about to be released without notes from a changelog.
+///
+/// Only commits that touch the package directory are considered.
+fn generate_release_notes(
+    params: &mut Params,
+    packages: &mut [Package],
+    tags: &[&str],
+) -> Result<()> {
+    // A `vX.Y.Z` tag only tells the previous release of the package if there's no other package.
+    let single_package = packages.len() == 1;
+    for package in packages {
+        let Package { name, version, .. } = &*package;
+        if package.release_notes.is_some()
+            || tags.contains(&&*format!("v{version}"))
+            || tags.contains(&&*format!("{name}-v{version}"))
+        {
             continue;
+        }
+        let Some(version) = Version::parse(version) else {
+            bail!("version `{version}` of package `{name}` is not a valid semantic version");
         };
 
-        let pkg = packages.iter_mut().find(|p| p.name == pkg).expect(&format!(
-            "`{k}` is set, but package {pkg} doesn't exist in the workspace"
-        ));
+        // The previous release is the tag of the newest version before this one.
+        let package_prefix = format!("{name}-v");
+        let previous = tags
+            .iter()
+            .filter_map(|tag| {
+                let v = tag
+                    .strip_prefix(&package_prefix)
+                    .or_else(|| tag.strip_prefix('v').filter(|_| single_package))?;
+                Some((Version::parse(v)?, *tag))
+            })
+            .filter(|(v, _)| *v < version)
+            .max();
+        let range = match previous {
+            Some((_, tag)) => format!("{tag}..HEAD"),
+            None => "HEAD".to_string(),
+        };
+        let dir = package
+            .manifest_path
+            .parent()
+            .and_then(|dir| dir.strip_prefix(&params.cwd).ok())
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+
+        println!("generating release notes for {package} from commits in {range}");
+        // The directory is passed as a separate argument, since it may contain spaces.
+        let log = params.shell_output_args(&[
+            "git",
+            "log",
+            commits::LOG_FORMAT,
+            &range,
+            "--",
+            &dir.to_string_lossy(),
+        ])?;
+        package.release_notes = commits::release_notes(&log);
+    }
+    Ok(())
+}
+
+/// Finds the release attachments of the packages.
+///
+/// With `other_workspaces`, attachments of packages that aren't in `packages` belong to another
+/// workspace, and are skipped.
+fn extract_release_attachments(
+    packages: &mut [Package],
+    attachments: &BTreeMap<String, String>,
+    config: &CargoConfig,
+    other_workspaces: bool,
+) -> Result<()> {
+    for (pkg, v) in attachments {
+        let k = format!("ATTACHMENTS_{pkg}");
+
+        let Some(pkg) = packages.iter_mut().find(|p| p.name == *pkg) else {
+            if other_workspaces {
+                continue;
+            }
+            bail!("`{k}` is set, but package {pkg} doesn't exist in the workspace");
+        };
         assert!(
             pkg.release_notes.is_some(),
             "cannot use `{k}` with a package that doesn't have release notes (as no release will be created)",
@@ -501,9 +1148,11 @@ fn extract_release_attachments(packages: &mut [Package]) -> Result<()> {
         );
 
         for file in v.split(':') {
-            let meta = fs::metadata(file).map_err(|e| format!("couldn't open `{file}`: {e}"))?;
+            let file = config.artifact_path(Path::new(file));
+            let meta = fs::metadata(&file)
+                .map_err(|e| format!("couldn't open `{}`: {e}", file.display()))?;
             if meta.file_type().is_dir() {
-                for res in fs::read_dir(file)? {
+                for res in fs::read_dir(&file)? {
                     let entry = res?;
                     assert!(
                         entry.metadata()?.file_type().is_file(),
@@ -513,7 +1162,7 @@ fn extract_release_attachments(packages: &mut [Package]) -> Result<()> {
                     pkg.release_attachments.push(entry.path());
                 }
             } else {
-                pkg.release_attachments.push(file.into());
+                pkg.release_attachments.push(file);
             }
         }
 
@@ -538,10 +1187,121 @@ fn extract_release_attachments(packages: &mut [Package]) -> Result<()> {
     Ok(())
 }
 
+/// Validates list-valued and version metadata fields that crates.io or Cargo would reject.
+///
+/// Fields inherited from the workspace (`field.workspace = true`) are skipped.
+fn check_package_metadata(package: &Package, toml: &Toml) -> Result<()> {
+    let name = &package.name;
+    for field in ["keywords", "categories"] {
+        let path = ["package", field];
+        let Some(value) = package.fields.get(field) else {
+            continue;
+        };
+        let Some(list) = value.as_str_array() else {
+            let msg = format!(
+                "`{field}` of package `{name}` must be an array of strings, but is a {}",
+                value.type_name()
+            );
+            return Err(toml.error(&path, msg).into());
+        };
+        if list.len() > 5 {
+            let msg = format!(
+                "package `{name}` has {} {field}, but crates.io allows at most 5",
+                list.len()
+            );
+            return Err(toml.error(&path, msg).into());
+        }
+        if field == "keywords" {
+            for keyword in list {
+                let valid = keyword.len() <= 20
+                    && keyword.starts_with(|c: char| c.is_ascii_alphanumeric())
+                    && keyword
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+'));
+                if !valid {
+                    let msg = format!("package `{name}` has an invalid keyword `{keyword}`");
+                    return Err(toml.error(&path, msg).into());
+                }
+            }
+        }
+    }
+
+    // Without quotes, `edition = 2021` is an integer and `rust-version = 1.70` a float.
+    for field in ["edition", "rust-version"] {
+        let Some(value) = package.fields.get(field) else {
+            continue;
+        };
+        if value.as_str().is_some() {
+            continue;
+        }
+        let msg = if let Some(n) = value.as_integer() {
+            format!("`{field}` of package `{name}` must be a string, like `{field} = \"{n}\"`")
+        } else if value.as_float().is_some() || value.as_datetime().is_some() {
+            format!("`{field}` of package `{name}` must be a string, so it needs quotes")
+        } else {
+            format!(
+                "`{field}` of package `{name}` must be a string, but is a {}",
+                value.type_name()
+            )
+        };
+        return Err(toml.error(&["package", field], msg).into());
+    }
+
+    let path = ["package", "rust-version"];
+    let Some(rust_version) = package.fields.get("rust-version").and_then(Value::as_str) else {
+        return Ok(());
+    };
+    let Some(version) = parse_rust_version(rust_version) else {
+        let msg = format!(
+            "package `{name}` has an invalid `rust-version` (expected a version like \"1.70\")"
+        );
+        return Err(toml.error(&path, msg).into());
+    };
+    let edition = package
+        .fields
+        .get("edition")
+        .and_then(Value::as_str)
+        .unwrap_or("2015");
+    let min = match edition {
+        "2018" => (1, 31, 0),
+        "2021" => (1, 56, 0),
+        "2024" => (1, 85, 0),
+        _ => (1, 0, 0),
+    };
+    if version < min {
+        let msg = format!(
+            "package `{name}` has `rust-version` {rust_version}, but edition {edition} requires at least {}.{}",
+            min.0, min.1,
+        );
+        return Err(toml.error(&path, msg).into());
+    }
+
+    Ok(())
+}
+
+/// Parses a `rust-version` like `1.70` or `1.70.1`.
+fn parse_rust_version(version: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = version.split('.').map(|p| p.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next()??;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch))
+}
+
 impl Params {
     fn shell_output(&mut self, cmd: &str) -> Result<String> {
+        self.shell_output_args(&cmd.split_ascii_whitespace().collect::<Vec<_>>())
+    }
+
+    /// Like `shell_output`, but with the program and its arguments already split, so that they
+    /// may contain whitespace.
+    fn shell_output_args(&mut self, args: &[&str]) -> Result<String> {
         match &mut self.mock_output {
             Some(output) => {
+                let cmd = args.join(" ");
                 if output.is_empty() {
                     panic!("missing entry for '{cmd}' in mock output list");
                 }
@@ -550,7 +1310,11 @@ impl Params {
                 Ok(output)
             }
             None => {
-                let output = command(cmd).stderr(Stdio::inherit()).output()?;
+                let (program, args) = args.split_first().unwrap();
+                let mut command = Command::new(program);
+                command.args(args);
+                setup_environment(program, &mut command);
+                let output = command.stderr(Stdio::inherit()).output()?;
                 check_status(output.status)?;
                 let res = String::from_utf8(output.stdout)?;
                 let res = res.trim().to_string();
@@ -571,11 +1335,36 @@ struct Package {
     /// If the package has its own changelog (separate from the top-level workspace changelog),
     /// this is the changelog's path.
     changelog_path: Option<PathBuf>,
+    /// If the package has its own changelog fragment directory (separate from the workspace's),
+    /// this is the directory's path.
+    fragments_path: Option<PathBuf>,
     /// Set to the changelog contents for this package version when we're about to publish it.
     release_notes: Option<String>,
     release_attachments: Vec<PathBuf>,
     /// Contents of the package's `Cargo.toml`.
     manifest: String,
+    manifest_path: PathBuf,
+    /// The keys of the `[package]` table, with `key.workspace = true` replaced by the value from
+    /// `[workspace.package]`.
+    fields: BTreeMap<String, Value>,
+    /// The registries the package may be published to (`publish = [...]`), or `None` if that isn't
+    /// restricted.
+    registries: Option<Vec<String>>,
+    dependencies: Vec<Dependency>,
+}
+
+/// A dependency of a [`Package`].
+#[derive(Debug, Clone)]
+struct Dependency {
+    /// Name of the package depended on, which differs from the key if the dependency is renamed.
+    package: String,
+    /// The dependency's directory, if it is a path dependency.
+    path: Option<PathBuf>,
+    version: Option<String>,
+    /// Whether this is a dev-dependency, which `cargo publish` strips.
+    dev: bool,
+    /// Path of the dependency's key in the manifest, for error messages.
+    decl: Vec<String>,
 }
 
 impl fmt::Display for Package {
@@ -589,131 +1378,724 @@ impl fmt::Debug for Package {
     }
 }
 
+/// Keys of `[package]` that can be inherited from `[workspace.package]`.
+const INHERITABLE_FIELDS: &[&str] = &[
+    "authors",
+    "categories",
+    "description",
+    "documentation",
+    "edition",
+    "exclude",
+    "homepage",
+    "include",
+    "keywords",
+    "license",
+    "license-file",
+    "publish",
+    "readme",
+    "repository",
+    "rust-version",
+    "version",
+];
+
+/// Keys of `[package]` that are read from `cargo metadata`, and the names `cargo metadata` uses
+/// for them.
+const METADATA_FIELDS: &[(&str, &str)] = &[
+    ("authors", "authors"),
+    ("categories", "categories"),
+    ("description", "description"),
+    ("documentation", "documentation"),
+    ("edition", "edition"),
+    ("homepage", "homepage"),
+    ("keywords", "keywords"),
+    ("license", "license"),
+    ("license-file", "license_file"),
+    ("readme", "readme"),
+    ("repository", "repository"),
+    ("rust-version", "rust_version"),
+    ("version", "version"),
+];
+
 #[derive(Debug)]
 struct Workspace {
     cwd: PathBuf,
-    version: Option<String>,
+    /// Contents of the `[workspace.package]` table of the root manifest.
+    package: Option<Table>,
+    /// Contents of the `[workspace.dependencies]` table of the root manifest.
+    dependencies: Option<Table>,
     changelog: Option<PathBuf>,
+    /// Where the workspace changelog is, or would be if it existed.
+    changelog_location: PathBuf,
+    /// The `package-changelog` setting: the path of the package changelogs, relative to the
+    /// workspace root, with `{name}` standing for the package name.
+    package_changelog: Option<String>,
+    /// The workspace's changelog fragment directory, if it exists.
+    fragments: Option<PathBuf>,
+    /// Start and end markers of blocks that are removed from release notes.
+    excluded_blocks: Vec<(String, String)>,
+    /// The `workspace.members` globs, or `None` if the root manifest has no `[workspace]` table.
+    members: Option<Vec<String>>,
+    /// The `workspace.exclude` paths, relative to the workspace root.
+    exclude: Vec<String>,
+    /// The `workspace.default-members` globs.
+    default_members: Vec<String>,
+    /// The `exclude` setting (from the workspace or root package metadata): globs of directories
+    /// whose packages are never published.
+    excluded_packages: Vec<String>,
 }
 
 impl Workspace {
     fn get(cwd: PathBuf) -> Result<Self> {
-        let version = match fs::read_to_string(cwd.join("Cargo.toml")) {
-            Ok(manifest) => match Toml(&manifest).get_field("package.version") {
-                Ok(version) => Some(
-                    version
-                        .as_str()
-                        .ok_or("version is not a string")?
-                        .to_string(),
-                ),
-                Err(_) => Toml(&manifest)
-                    .section("workspace.package")
-                    .and_then(|toml| {
-                        toml.get_field("version")
-                            .ok()
-                            .and_then(|fld| fld.as_str().map(ToString::to_string))
-                    }),
-            },
+        let manifest_path = cwd.join("Cargo.toml");
+        fn workspace_table(toml: &Toml, key: &str) -> Result<Option<Table>> {
+            let path = ["workspace", key];
+            match toml.get_path(&path) {
+                Ok(Value::Table(table)) => Ok(Some(table.clone())),
+                Ok(value) => {
+                    let msg = format!(
+                        "`workspace.{key}` must be a table, but is a {}",
+                        value.type_name()
+                    );
+                    Err(toml.error(&path, msg).into())
+                }
+                Err(_) => Ok(None),
+            }
+        }
+
+        let manifest = match fs::read_to_string(&manifest_path) {
+            Ok(manifest) => Some(manifest),
             Err(e) if e.kind() == io::ErrorKind::NotFound => None,
             Err(e) => return Err(e.into()),
         };
+        let toml = manifest
+            .as_deref()
+            .map(|manifest| Toml::parse_file(manifest, &manifest_path))
+            .transpose()?;
+        let (mut package, mut dependencies) = (None, None);
+        let (mut changelog, mut package_changelog, mut fragments) = (None, None, None);
+        let mut excluded_blocks = Vec::new();
+        let (mut members, mut exclude, mut default_members) = (None, Vec::new(), Vec::new());
+        let mut excluded_packages = Vec::new();
+        if let Some(toml) = &toml {
+            package = workspace_table(toml, "package")?;
+            dependencies = workspace_table(toml, "dependencies")?;
+            // The changelog of a root package is the workspace changelog, so that a single-package
+            // repository with a differently named changelog still gets `vX.Y.Z` tags.
+            changelog = match metadata_setting(toml, "workspace", "changelog")? {
+                Some(path) => Some(path),
+                None => metadata_setting(toml, "package", "changelog")?,
+            };
+            package_changelog = metadata_setting(toml, "workspace", "package-changelog")?;
+            fragments = metadata_setting(toml, "workspace", "changelog-fragments")?;
+            excluded_blocks = release_notes_exclude(toml)?;
+            if toml.get_path(&["workspace"]).is_ok() {
+                members = Some(string_array(toml, &["workspace", "members"])?);
+                exclude = string_array(toml, &["workspace", "exclude"])?;
+                default_members = string_array(toml, &["workspace", "default-members"])?;
+            }
+            // Like `release-notes-exclude`, this can be set for a single-package repository too.
+            if let Some(path) = ["workspace", "package"]
+                .map(|table| [table, "metadata", "sludge-cicd", "exclude"])
+                .into_iter()
+                .find(|path| toml.get_path(path).is_ok())
+            {
+                excluded_packages = string_array(toml, &path)?;
+            }
+        }
 
-        let changelog = cwd.join("CHANGELOG.md");
-        let changelog = if changelog.exists() {
-            Some(changelog)
-        } else {
-            None
-        };
+        let changelog_location = cwd.join(changelog.unwrap_or(DEFAULT_CHANGELOG));
+        let changelog = changelog_location
+            .exists()
+            .then(|| changelog_location.clone());
+        let package_changelog = package_changelog.map(String::from);
+
+        let fragments = cwd.join(fragments.unwrap_or(fragments::DEFAULT_DIR));
+        let fragments = fragments.is_dir().then_some(fragments);
 
         Ok(Self {
             cwd,
-            version,
+            package,
+            dependencies,
             changelog,
+            changelog_location,
+            package_changelog,
+            fragments,
+            excluded_blocks,
+            members,
+            exclude,
+            default_members,
+            excluded_packages,
         })
     }
 
+    /// Returns where the changelog of the package in `dir` is, or would be if it existed.
+    ///
+    /// That's the package's `changelog` setting, the workspace's `package-changelog` setting, or
+    /// `CHANGELOG.md` in the package directory.
+    fn package_changelog_location(&self, toml: &Toml, dir: &Path, name: &str) -> Result<PathBuf> {
+        if let Some(path) = metadata_setting(toml, "package", "changelog")? {
+            return Ok(dir.join(path));
+        }
+        Ok(match &self.package_changelog {
+            Some(pattern) => self.cwd.join(pattern.replace("{name}", name)),
+            None => dir.join(DEFAULT_CHANGELOG),
+        })
+    }
+
+    /// Resolves the fields of a package's `[package]` table, substituting the workspace's values
+    /// for those marked with `key.workspace = true`.
+    fn resolve_package_fields(&self, toml: &Toml) -> Result<BTreeMap<String, Value>> {
+        let Ok(Value::Table(package)) = toml.get_path(&["package"]) else {
+            return Ok(BTreeMap::new());
+        };
+
+        let mut fields = BTreeMap::new();
+        for (key, value) in package.iter() {
+            let inherits = match value {
+                Value::Table(table) if INHERITABLE_FIELDS.contains(&key) => {
+                    table.get("workspace").and_then(Value::as_bool) == Some(true)
+                }
+                _ => false,
+            };
+            if !inherits {
+                fields.insert(key.to_string(), value.clone());
+                continue;
+            }
+
+            match self.package.as_ref().and_then(|table| table.get(key)) {
+                Some(value) => {
+                    fields.insert(key.to_string(), value.clone());
+                }
+                None => {
+                    let name = package.get("name").and_then(Value::as_str).unwrap_or("?");
+                    let msg = format!(
+                        "package `{name}` inherits `{key}`, but the workspace does not define `workspace.package.{key}`"
+                    );
+                    return Err(toml.error(&["package", key, "workspace"], msg).into());
+                }
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Collects the dependencies of a package, looking up those declared with `workspace = true` in
+    /// `[workspace.dependencies]`.
+    fn resolve_dependencies(&self, toml: &Toml, dir: &Path) -> Result<Vec<Dependency>> {
+        let mut deps = Vec::new();
+        for (table_path, table) in dependency_tables(toml.root()) {
+            for (key, value) in table.iter() {
+                let mut decl = table_path.clone();
+                decl.push(key);
+
+                let inherits = value
+                    .as_table()
+                    .and_then(|table| table.get("workspace"))
+                    .and_then(Value::as_bool)
+                    == Some(true);
+                // Paths in `[workspace.dependencies]` are relative to the workspace root.
+                let (spec, base) = if inherits {
+                    match self.dependencies.as_ref().and_then(|table| table.get(key)) {
+                        Some(spec) => (spec, &*self.cwd),
+                        None => {
+                            let msg = format!(
+                                "dependency `{key}` is inherited, but the workspace does not define `workspace.dependencies.{key}`"
+                            );
+                            return Err(toml.error(&decl, msg).into());
+                        }
+                    }
+                } else {
+                    (value, dir)
+                };
+
+                let (package, path, version) = match spec {
+                    Value::Str(version) => (None, None, Some(version.clone())),
+                    Value::Table(spec) => (
+                        spec.get("package").and_then(Value::as_str),
+                        spec.get("path").and_then(Value::as_str),
+                        spec.get("version")
+                            .and_then(Value::as_str)
+                            .map(String::from),
+                    ),
+                    _ => {
+                        let msg = format!(
+                            "dependency `{key}` must be a string or a table, but is a {}",
+                            spec.type_name()
+                        );
+                        return Err(toml.error(&decl, msg).into());
+                    }
+                };
+                let path = path.map(|path| {
+                    let path = base.join(path);
+                    fs::canonicalize(&path).unwrap_or(path)
+                });
+
+                deps.push(Dependency {
+                    package: package.unwrap_or(key).to_string(),
+                    path,
+                    version,
+                    dev: table_path.last() == Some(&"dev-dependencies"),
+                    decl: decl.iter().map(|s| s.to_string()).collect(),
+                });
+            }
+        }
+        Ok(deps)
+    }
+
     /// Enumerates all publishable packages in the workspace.
     ///
+    /// The workspace members are determined like Cargo does: the root package, the directories
+    /// matching the `workspace.members` globs, and (transitively) the path dependencies of members
+    /// that are inside the workspace directory, except those under a `workspace.exclude` path.
+    /// Without a `[workspace]` table, the root package is the only member.
+    ///
     /// A package is considered publishable if it does not set `publish = false` and it contains a
     /// `package.version` key.
     fn find_packages(&self) -> Result<Vec<Package>> {
-        fn recurse(dir: PathBuf, out: &mut Vec<Package>, workspace: &Workspace) -> Result<()> {
-            let mut toml = dir.clone();
-            toml.push("Cargo.toml");
-            if toml.exists() {
-                let manifest = fs::read_to_string(&toml)?;
-                if let Some(toml) = Toml(&manifest).section("package") {
-                    // Filter out virtual manifests, those with `publish = false` set, and those that lack a
-                    // `version` field.
-                    if !matches!(toml.get_field("publish"), Ok(Value::Bool(false)))
-                        && (toml.get_field("version").is_ok()
-                            || toml.get_field("version.workspace").is_ok())
-                    {
-                        let name = toml
-                            .get_field("name")?
-                            .as_str()
-                            .ok_or("package name is not a string")?
-                            .to_string();
-                        let version = match toml.get_field("version") {
-                            Ok(version) => version
-                                .as_str()
-                                .ok_or("version is not a string")?
-                                .to_string(),
-                            Err(e) => match &workspace.version {
-                                Some(version) => version.clone(),
-                                None => return Err(e),
-                            },
-                        };
-
-                        let mut changelog = dir.clone();
-                        changelog.push("CHANGELOG.md");
-                        let changelog = if changelog.exists()
-                            && Some(&*changelog) != workspace.changelog.as_deref()
-                        {
-                            Some(changelog)
-                        } else {
-                            None
-                        };
-
-                        out.push(Package {
-                            name,
-                            version,
-                            changelog_path: changelog,
-                            release_notes: None,
-                            release_attachments: Vec::new(),
-                            manifest,
-                        });
+        if !self.cwd.join("Cargo.toml").exists() {
+            return Err("`Cargo.toml` does not exist in the project directory".into());
+        }
+
+        let root = fs::canonicalize(&self.cwd)?;
+        let mut dirs = vec![self.cwd.clone()];
+        dirs.extend(self.member_dirs()?);
+
+        let mut members = BTreeSet::new();
+        let mut out = Vec::new();
+        let mut i = 0;
+        while let Some(dir) = dirs.get(i).cloned() {
+            i += 1;
+            if !members.insert(fs::canonicalize(&dir)?) {
+                continue;
+            }
+
+            let manifest_path = dir.join("Cargo.toml");
+            let manifest = fs::read_to_string(&manifest_path)?;
+            let parsed = Toml::parse_file(&manifest, &manifest_path)?;
+            let fields = self.resolve_package_fields(&parsed)?;
+            let dependencies = self.resolve_dependencies(&parsed, &dir)?;
+
+            if self.members.is_some() {
+                for path in dependencies.iter().filter_map(|dep| dep.path.as_deref()) {
+                    let Ok(relative) = path.strip_prefix(&root) else {
+                        continue;
+                    };
+                    if path.join("Cargo.toml").exists() && !self.is_excluded(relative) {
+                        dirs.push(self.cwd.join(relative));
                     }
                 }
             }
 
-            let is_test_dir = dir
-                .to_str()
-                .expect("non-UTF-8 path")
-                .ends_with("sludge-cicd-test-projects");
-
-            if !is_test_dir {
-                for entry in fs::read_dir(&dir)? {
-                    let entry = entry?;
-                    if entry.file_type()?.is_dir() {
-                        recurse(entry.path(), out, workspace)?;
+            // `publish = false` is the same as an empty list of registries.
+            let registries = match fields.get("publish") {
+                None => None,
+                Some(Value::Bool(publish)) => (!publish).then(Vec::new),
+                Some(value) => match value.as_str_array() {
+                    Some(registries) => Some(registries.into_iter().map(String::from).collect()),
+                    None => {
+                        let msg = format!(
+                            "`publish` must be a boolean or an array of strings, but is a {}",
+                            value.type_name()
+                        );
+                        return Err(parsed.error(&["package", "publish"], msg).into());
                     }
+                },
+            };
+
+            // Filter out virtual manifests, those that can't be published to any registry, and
+            // those that lack a `version` field.
+            let is_publishable = registries.as_ref().is_none_or(|r| !r.is_empty());
+            if let (true, Some(version)) = (is_publishable, fields.get("version")) {
+                let name = parsed.get_str(&["package", "name"])?.to_string();
+                let Some(version) = version.as_str() else {
+                    let msg = format!(
+                        "`version` of package `{name}` must be a string, but is a {}",
+                        version.type_name()
+                    );
+                    return Err(parsed.error(&["package", "version"], msg).into());
+                };
+                let version = version.to_string();
+                if !self.is_left_out(&name, &manifest_path, None) {
+                    out.push(self.package(
+                        name,
+                        version,
+                        manifest_path,
+                        fields,
+                        registries,
+                        dependencies,
+                    )?);
                 }
             }
-            Ok(())
         }
 
-        if !self.cwd.join("Cargo.toml").exists() {
-            return Err("`Cargo.toml` does not exist in the project directory".into());
+        for pattern in &self.default_members {
+            for dir in self.expand_members(pattern)? {
+                let relative = dir.strip_prefix(&self.cwd).unwrap_or(&dir);
+                let is_member = fs::canonicalize(&dir).is_ok_and(|dir| members.contains(&dir));
+                // Default members matched by a glob are allowed to be excluded.
+                if !is_member && !self.is_excluded(relative) {
+                    bail!(
+                        "package `{}` is listed in `workspace.default-members`, but is not a member of the workspace",
+                        relative.display(),
+                    );
+                }
+            }
         }
 
+        let pkgs = sort_packages(&mut out);
+
+        Ok(pkgs)
+    }
+
+    /// Enumerates all publishable packages in the workspace, as reported by `cargo metadata`.
+    ///
+    /// Tests don't run Cargo, so they use [`Workspace::find_packages`] instead.
+    fn packages(&self) -> Result<Vec<Package>> {
+        if cfg!(test) {
+            return self.find_packages();
+        }
+
+        let output = command("cargo metadata --format-version 1 --no-deps")
+            .current_dir(&self.cwd)
+            .stderr(Stdio::inherit())
+            .output()?;
+        check_status(output.status)?;
+        let metadata = String::from_utf8(output.stdout)?;
+
+        let output = command("git ls-files -z")
+            .current_dir(&self.cwd)
+            .stderr(Stdio::inherit())
+            .output()?;
+        check_status(output.status)?;
+        let files = String::from_utf8(output.stdout)?;
+        let tracked = files.split('\0').map(Path::new).collect();
+
+        self.packages_from_metadata(&metadata, Some(&tracked))
+    }
+
+    /// Creates the publishable packages from the output of
+    /// `cargo metadata --format-version 1 --no-deps`.
+    ///
+    /// Cargo has already resolved workspace membership, inheritance and renamed dependencies, and
+    /// reports packages without a `version` as `publish = false`.
+    ///
+    /// `tracked` are the files tracked by git (relative to the workspace root), if packages whose
+    /// manifest isn't tracked should be left out.
+    fn packages_from_metadata(
+        &self,
+        metadata: &str,
+        tracked: Option<&BTreeSet<&Path>>,
+    ) -> Result<Vec<Package>> {
+        fn invalid() -> Error {
+            "unexpected `cargo metadata` output".into()
+        }
+        fn str<'a>(json: &'a Json, key: &str) -> Result<&'a str> {
+            json.get(key).and_then(Json::as_str).ok_or_else(invalid)
+        }
+
+        let metadata = Json::parse(metadata)?;
+
+        // Cargo reports absolute paths, which are made relative to the workspace like ours.
+        let root = fs::canonicalize(&self.cwd)?;
+        let local_path = |path: &str| {
+            let path = Path::new(path);
+            match path.strip_prefix(&root) {
+                Ok(relative) => self.cwd.join(relative),
+                Err(_) => path.to_path_buf(),
+            }
+        };
+
+        let members = metadata
+            .get("workspace_members")
+            .and_then(Json::as_array)
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|id| id.as_str().ok_or_else(invalid))
+            .collect::<Result<Vec<_>>>()?;
         let mut out = Vec::new();
-        recurse(self.cwd.clone(), &mut out, self)?;
+        for package in metadata
+            .get("packages")
+            .and_then(Json::as_array)
+            .ok_or_else(invalid)?
+        {
+            if !members.contains(&str(package, "id")?) {
+                continue;
+            }
+            // `publish` is `null` if unrestricted, or the list of allowed registries.
+            let registries = match package.get("publish").ok_or_else(invalid)? {
+                Json::Null => None,
+                publish => Some(
+                    publish
+                        .as_array()
+                        .ok_or_else(invalid)?
+                        .iter()
+                        .map(|registry| registry.as_str().map(String::from).ok_or_else(invalid))
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+            };
+            if registries.as_ref().is_some_and(Vec::is_empty) {
+                continue;
+            }
+            let name = str(package, "name")?;
+            let manifest_path = local_path(str(package, "manifest_path")?);
+            if self.is_left_out(name, &manifest_path, tracked) {
+                continue;
+            }
+
+            let mut fields = BTreeMap::new();
+            for (key, field) in METADATA_FIELDS {
+                let value = match package.get(field) {
+                    Some(Json::Str(value)) => Value::Str(value.clone()),
+                    Some(Json::Array(values)) => Value::Array(
+                        values
+                            .iter()
+                            .filter_map(|value| value.as_str().map(|s| Value::Str(s.into())))
+                            .collect(),
+                    ),
+                    _ => continue,
+                };
+                fields.insert(key.to_string(), value);
+            }
+
+            let mut dependencies = Vec::new();
+            for dep in package
+                .get("dependencies")
+                .and_then(Json::as_array)
+                .ok_or_else(invalid)?
+            {
+                let name = str(dep, "name")?;
+                let path = dep.get("path").and_then(Json::as_str).map(|path| {
+                    let path = PathBuf::from(path);
+                    fs::canonicalize(&path).unwrap_or(path)
+                });
+                // Cargo reports a requirement of `*` if a path dependency has no `version`, and
+                // writes requirements like `1.0` as `^1.0`.
+                let version = Some(str(dep, "req")?)
+                    .filter(|req| path.is_none() || *req != "*")
+                    .map(|req| req.strip_prefix('^').unwrap_or(req).to_string());
+                let kind = dep.get("kind").and_then(Json::as_str);
+                let table = match kind {
+                    Some("dev") => "dev-dependencies",
+                    Some("build") => "build-dependencies",
+                    _ => "dependencies",
+                };
+                let key = dep.get("rename").and_then(Json::as_str).unwrap_or(name);
+                let mut decl = Vec::new();
+                if let Some(target) = dep.get("target").and_then(Json::as_str) {
+                    decl.extend(["target".to_string(), target.to_string()]);
+                }
+                decl.extend([table.to_string(), key.to_string()]);
+
+                dependencies.push(Dependency {
+                    package: name.to_string(),
+                    path,
+                    version,
+                    dev: kind == Some("dev"),
+                    decl,
+                });
+            }
+
+            out.push(self.package(
+                name.to_string(),
+                str(package, "version")?.to_string(),
+                manifest_path,
+                fields,
+                registries,
+                dependencies,
+            )?);
+        }
 
         let pkgs = sort_packages(&mut out);
 
         Ok(pkgs)
     }
+
+    /// Returns whether a package is left out because its directory matches the `exclude` setting,
+    /// or its manifest isn't one of the `tracked` files, and says why.
+    fn is_left_out(
+        &self,
+        name: &str,
+        manifest_path: &Path,
+        tracked: Option<&BTreeSet<&Path>>,
+    ) -> bool {
+        let relative = manifest_path
+            .strip_prefix(&self.cwd)
+            .unwrap_or(manifest_path);
+        let dir = relative.parent().unwrap_or(Path::new(""));
+        if let Some(pattern) = self
+            .excluded_packages
+            .iter()
+            .find(|pattern| glob::matches_path(pattern, dir))
+        {
+            println!("skipping {name}: excluded by `{pattern}`");
+            return true;
+        }
+        if tracked.is_some_and(|tracked| !tracked.contains(relative)) {
+            println!(
+                "skipping {name}: `{}` is not tracked by git",
+                relative.display()
+            );
+            return true;
+        }
+        false
+    }
+
+    /// Creates a [`Package`], locating its changelog and fragment directory.
+    fn package(
+        &self,
+        name: String,
+        version: String,
+        manifest_path: PathBuf,
+        fields: BTreeMap<String, Value>,
+        registries: Option<Vec<String>>,
+        dependencies: Vec<Dependency>,
+    ) -> Result<Package> {
+        let manifest = fs::read_to_string(&manifest_path)?;
+        let parsed = Toml::parse_file(&manifest, &manifest_path)?;
+        let dir = manifest_path.parent().unwrap_or(Path::new(""));
+
+        let changelog = self.package_changelog_location(&parsed, dir, &name)?;
+        let changelog = (changelog.exists()
+            && !self
+                .changelog
+                .as_deref()
+                .is_some_and(|ws| same_file(&changelog, ws)))
+        .then_some(changelog);
+
+        let fragments = dir.join(
+            metadata_setting(&parsed, "package", "changelog-fragments")?
+                .unwrap_or(fragments::DEFAULT_DIR),
+        );
+        let fragments = (fragments.is_dir() && Some(&*fragments) != self.fragments.as_deref())
+            .then_some(fragments);
+
+        Ok(Package {
+            name,
+            version,
+            changelog_path: changelog,
+            fragments_path: fragments,
+            release_notes: None,
+            release_attachments: Vec::new(),
+            manifest,
+            manifest_path,
+            fields,
+            registries,
+            dependencies,
+        })
+    }
+
+    /// Expands the `workspace.members` globs into the directories of the members, leaving out
+    /// excluded ones.
+    fn member_dirs(&self) -> Result<Vec<PathBuf>> {
+        let mut dirs = Vec::new();
+        for pattern in self.members.iter().flatten() {
+            for dir in self.expand_members(pattern)? {
+                let relative = dir.strip_prefix(&self.cwd).unwrap_or(&dir);
+                if self.is_excluded(relative) {
+                    continue;
+                }
+                if !dir.join("Cargo.toml").exists() {
+                    bail!(
+                        "workspace member `{}` (from `{pattern}` in `workspace.members`) has no `Cargo.toml`",
+                        relative.display(),
+                    );
+                }
+                dirs.push(dir);
+            }
+        }
+        Ok(dirs)
+    }
+
+    /// Expands a `workspace.members` or `workspace.default-members` glob into the matching
+    /// directories.
+    ///
+    /// Like in Cargo, a path without special characters is returned even if it doesn't exist.
+    fn expand_members(&self, pattern: &str) -> Result<Vec<PathBuf>> {
+        if !glob::is_pattern(pattern) {
+            return Ok(vec![self.cwd.join(pattern)]);
+        }
+        let mut dirs = glob::expand(&self.cwd, pattern)?;
+        // Files matched by a glob (like `.DS_Store`) are ignored.
+        dirs.retain(|path| path.is_dir());
+        Ok(dirs)
+    }
+
+    /// Returns whether the directory at `relative` (relative to the workspace root) is excluded by
+    /// `workspace.exclude`.
+    ///
+    /// Like in Cargo, a directory that is listed in `workspace.members` as-is can't be excluded.
+    fn is_excluded(&self, relative: &Path) -> bool {
+        let under = |paths: &[String]| paths.iter().any(|path| relative.starts_with(path));
+        under(&self.exclude) && !self.members.as_deref().is_some_and(under)
+    }
+}
+
+/// Reads an array of paths (or globs), like `workspace.members`.
+fn string_array(toml: &Toml, path: &[&str]) -> Result<Vec<String>> {
+    let Ok(value) = toml.get_path(path) else {
+        return Ok(Vec::new());
+    };
+    let paths = value.as_array().and_then(|paths| {
+        paths
+            .iter()
+            .map(|path| path.as_str().map(String::from))
+            .collect::<Option<Vec<_>>>()
+    });
+    paths.ok_or_else(|| {
+        let msg = format!("`{}` must be an array of strings", path.join("."));
+        toml.error(path, msg).into()
+    })
+}
+
+/// Reads the `release-notes-exclude` setting, a list of `{ start = "...", end = "..." }` tables.
+///
+/// It is read from `[workspace.metadata.sludge-cicd]`, or `[package.metadata.sludge-cicd]` for
+/// single-package repositories.
+fn release_notes_exclude(toml: &Toml) -> Result<Vec<(String, String)>> {
+    let key = "release-notes-exclude";
+    let Some(path) = ["workspace", "package"]
+        .map(|table| [table, "metadata", "sludge-cicd", key])
+        .into_iter()
+        .find(|path| toml.get_path(path).is_ok())
+    else {
+        return Ok(Vec::new());
+    };
+
+    let markers = toml.get_path(&path)?.as_array().and_then(|blocks| {
+        blocks
+            .iter()
+            .map(|block| {
+                let marker = |name| block.as_table()?.get(name)?.as_str().map(String::from);
+                Some((marker("start")?, marker("end")?))
+            })
+            .collect::<Option<Vec<_>>>()
+    });
+    markers.ok_or_else(|| {
+        let msg = format!(
+            "`{key}` must be an array of tables with `start` and `end` strings, like `[{{ start = \"<!-- internal -->\", end = \"<!-- /internal -->\" }}]`"
+        );
+        toml.error(&path, msg).into()
+    })
+}
+
+/// The changelog file name that is used unless another one is configured.
+const DEFAULT_CHANGELOG: &str = "CHANGELOG.md";
+
+/// Whether `a` and `b` refer to the same file, even if the paths differ.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Reads a string setting from the `[package.metadata.sludge-cicd]` or
+/// `[workspace.metadata.sludge-cicd]` table, depending on `table`.
+fn metadata_setting<'a>(toml: &'a Toml, table: &str, key: &str) -> Result<Option<&'a str>> {
+    let path = [table, "metadata", "sludge-cicd", key];
+    match toml.get_path(&path) {
+        Ok(_) => Ok(Some(toml.get_str(&path)?)),
+        Err(_) => Ok(None),
+    }
 }
 
 /// A package can only be published (even with `--no-verify`) if its dependencies are already
@@ -729,33 +2111,31 @@ fn sort_packages(pkgs: &mut [Package]) -> Vec<Package> {
     // Start with a deterministic ordering.
     pkgs.sort_by_key(|pkg| pkg.name.clone());
 
+    let dirs = pkgs
+        .iter()
+        .map(|pkg| {
+            let dir = pkg.manifest_path.parent().unwrap();
+            fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf())
+        })
+        .collect::<Vec<_>>();
+
     let mut depends_on = vec![vec![]; pkgs.len()];
     let mut dependants = vec![0; pkgs.len()];
     for (i, pkg) in pkgs.iter().enumerate() {
-        let toml = Toml(&pkg.manifest);
-        for (name, contents) in toml.sections() {
-            // FIXME: allow specifications like [target.'cfg(...)'.dependencies]
-            // FIXME: allow specifications like [dependencies.dep]\nversion = ...
-            if !name.ends_with("dependencies") {
-                continue;
-            }
-
-            for line in contents.0.lines() {
-                // Dependency specifications like:
-                // dep = { version = ... }
-                // dep = "1.2.3"
-                // dep.workspace = true
-                // dep.version = "1.2.3"
-                let Some((dep, _)) = line.split_once('=') else {
-                    continue;
-                };
-                let dep = dep.split('.').next().unwrap().trim();
-                if let Some((pos, dep)) = pkgs.iter().enumerate().find(|(_, pkg)| pkg.name == dep) {
-                    if !depends_on[i].contains(&pos) {
-                        println!("{pkg} depends on {dep}");
-                        depends_on[i].push(pos);
-                        dependants[pos] += 1;
-                    }
+        for dep in &pkg.dependencies {
+            // Path dependencies are matched by directory, everything else by package name.
+            let found = pkgs
+                .iter()
+                .enumerate()
+                .find(|(pos, other)| match &dep.path {
+                    Some(path) => *path == dirs[*pos],
+                    None => other.name == dep.package,
+                });
+            if let Some((pos, dep)) = found {
+                if !depends_on[i].contains(&pos) {
+                    println!("{pkg} depends on {dep}");
+                    depends_on[i].push(pos);
+                    dependants[pos] += 1;
                 }
             }
         }
@@ -788,23 +2168,49 @@ fn sort_packages(pkgs: &mut [Package]) -> Vec<Package> {
     list
 }
 
+/// Returns all dependency tables in a package manifest and their paths, including target-specific
+/// ones.
+fn dependency_tables(manifest: &Table) -> Vec<(Vec<&str>, &Table)> {
+    const KINDS: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+    let mut tables = KINDS
+        .iter()
+        .filter_map(|kind| Some((vec![*kind], manifest.get(kind)?.as_table()?)))
+        .collect::<Vec<_>>();
+    if let Some(targets) = manifest.get("target").and_then(Value::as_table) {
+        for (name, target) in targets.iter() {
+            if let Some(target) = target.as_table() {
+                tables.extend(KINDS.iter().filter_map(|kind| {
+                    Some((vec!["target", name, kind], target.get(kind)?.as_table()?))
+                }));
+            }
+        }
+    }
+    tables
+}
+
 fn shell(cmd: &str) -> Result<()> {
     shell_with_stdin(cmd, "")
 }
 
 fn shell_with_stdin(cmd: &str, stdin: &str) -> Result<()> {
-    shell_ex(cmd, stdin, false)
+    shell_ex(cmd, stdin, false, None)
 }
 
-fn shell_ex(cmd: &str, stdin: &str, sudo: bool) -> Result<()> {
+/// Runs a command, in `dir` (relative to the current directory) if given.
+fn shell_ex(cmd: &str, stdin: &str, sudo: bool, dir: Option<&Path>) -> Result<()> {
     assert!(
         !cmd.contains('"'),
         "quoting and escaping command-line arguments is not supported"
     );
 
     print!(
-        "> {sudo}{cmd}",
-        sudo = sudo.then_some("sudo ").unwrap_or(""),
+        "> {cd}{sudo}{cmd}",
+        cd = match dir {
+            Some(dir) => format!("cd {} && ", dir.display()),
+            None => String::new(),
+        },
+        sudo = if sudo { "sudo " } else { "" },
         cmd = cmd.trim(),
     );
     if stdin.is_empty() {
@@ -822,7 +2228,11 @@ fn shell_ex(cmd: &str, stdin: &str, sudo: bool) -> Result<()> {
     if cfg!(test) {
         Ok(())
     } else {
-        let mut child = command_ex(cmd, sudo)
+        let mut command = command_ex(cmd, sudo);
+        if let Some(dir) = dir {
+            command.current_dir(dir);
+        }
+        let mut child = command
             .stdin(Stdio::piped())
             .spawn()
             .map_err(|e| format!("failed to execute '{cmd}': {e}"))?;
@@ -882,6 +2292,13 @@ fn setup_environment(program: &str, cmd: &mut Command) {
     // The same goes for the `GITHUB_TOKEN`.
     cmd.env_remove("GITHUB_TOKEN");
 
+    // And for the tokens of other registries.
+    for (key, _) in env::vars_os() {
+        if key.to_str().and_then(registry_token_name).is_some() {
+            cmd.env_remove(key);
+        }
+    }
+
     match program {
         "cargo" => {
             let rustflags = env::var_os("RUSTFLAGS").unwrap_or("-D warnings".into());
@@ -903,6 +2320,12 @@ fn setup_environment(program: &str, cmd: &mut Command) {
     }
 }
 
+/// Returns the registry name in a `CARGO_REGISTRIES_<NAME>_TOKEN` variable.
+fn registry_token_name(key: &str) -> Option<&str> {
+    key.strip_prefix("CARGO_REGISTRIES_")?
+        .strip_suffix("_TOKEN")
+}
+
 fn check_status(status: ExitStatus) -> Result<()> {
     if !status.success() {
         Err(format!("$status: {}", status))?;
@@ -1,4 +1,4 @@
-use std::fmt;
+use std::{fmt, ops::Range};
 
 use crate::utils::next_line;
 
@@ -11,56 +11,661 @@ impl<'a> fmt::Debug for Markdown<'a> {
 }
 
 impl<'a> Markdown<'a> {
+    /// Splits the document at all headings of the given level.
+    ///
+    /// Returns the title of each heading, and the text between it and the next heading of the
+    /// same level.
     pub fn sections(&self, level: u8) -> Vec<(&'a str, Markdown<'a>)> {
-        // `Lines` has no stable `remainder` or `as_str` method, so we have to do this manually...
-        let mut remainder = self.0;
+        let headings = self
+            .headings()
+            .into_iter()
+            .filter(|heading| heading.level == level)
+            .collect::<Vec<_>>();
+        headings
+            .iter()
+            .enumerate()
+            .map(|(i, heading)| {
+                let end = headings.get(i + 1).map_or(self.0.len(), |next| next.start);
+                (heading.title, Markdown(self.0[heading.end..end].trim()))
+            })
+            .collect()
+    }
 
-        // Find the first line that starts a section.
-        let mut section_name = loop {
-            let Some(line) = next_line(&mut remainder) else {
-                return Vec::new();
-            };
-            if let Some(name) = start_of_section(level, line) {
-                break name;
+    /// Finds all ATX (`## Title`) and setext (`Title\n---`) headings in the document.
+    ///
+    /// Lines in fenced and indented code blocks are skipped. Headings nested in other blocks, like
+    /// block quotes or list items, are not recognized.
+    pub fn headings(&self) -> Vec<Heading<'a>> {
+        self.scan().headings
+    }
+
+    /// Removes HTML comments (`<!-- ... -->`) outside of code.
+    ///
+    /// Comments that are on lines of their own are removed along with those lines, and so is a
+    /// blank line that would otherwise follow another one.
+    pub fn remove_html_comments(&self) -> String {
+        let text = self.0;
+        let literal = literal_ranges(text, &self.scan().code);
+        let mut out = String::new();
+        let mut pos = 0;
+        let mut copied = 0;
+        while let Some(offset) = text[pos..].find("<!--") {
+            let mut start = pos + offset;
+            if let Some(range) = literal.iter().find(|range| range.contains(&start)) {
+                pos = range.end;
+                continue;
+            }
+            // An unclosed comment extends to the end of the document.
+            let mut end = text[start + 4..]
+                .find("-->")
+                .map_or(text.len(), |i| start + 4 + i + 3);
+            let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = text[end..].find('\n').map_or(text.len(), |i| end + i + 1);
+            if text[end..line_end].trim().is_empty() {
+                if text[line_start..start].trim().is_empty() {
+                    start = line_start;
+                    end = skip_blank_line(text, line_end, &text[..line_start]);
+                } else {
+                    // Don't leave trailing whitespace behind.
+                    start = text[..start].trim_end_matches([' ', '\t']).len();
+                }
             }
+            out += &text[copied..start];
+            copied = end;
+            pos = end;
+        }
+        out += &text[copied..];
+        out
+    }
+
+    /// Changes the levels of all headings, so that the highest-level one ends up at level `top`.
+    ///
+    /// The relative levels of headings are kept, but nothing can go below level 6. Headings that
+    /// have to be changed are turned into ATX headings.
+    pub fn relevel_headings(&self, top: u8) -> String {
+        let headings = self.headings();
+        let Some(highest) = headings.iter().map(|heading| heading.level).min() else {
+            return self.0.to_string();
         };
+        if highest == top {
+            return self.0.to_string();
+        }
+
+        let mut out = String::new();
+        let mut copied = 0;
+        for heading in headings {
+            let level = (heading.level + top - highest).min(6);
+            // Setext headings can span multiple lines, ATX headings can't.
+            let title = heading.title.lines().map(str::trim).collect::<Vec<_>>();
+            out += &self.0[copied..heading.start];
+            out += &"#".repeat(level.into());
+            out += " ";
+            out += &title.join(" ");
+            if self.0[..heading.end].ends_with('\n') {
+                out += "\n";
+            }
+            copied = heading.end;
+        }
+        out += &self.0[copied..];
+        out
+    }
 
-        let mut section_contents = &self.0[self.0.len() - remainder.len()..];
-        let mut out = Vec::new();
-        // Find the beginning of the next section, or the end of the file.
+    /// Finds all link reference definitions (`[label]: https://example.com`) in the document.
+    pub fn link_definitions(&self) -> Vec<LinkDefinition<'a>> {
+        self.scan().definitions
+    }
+
+    /// Replaces reference links like `[text][label]`, `[label][]` and `[label]` with inline links,
+    /// using the given link reference definitions.
+    ///
+    /// Link reference definitions in the document itself are removed. This makes the text
+    /// self-contained, so that it can be moved out of the document that defines the links.
+    pub fn resolve_reference_links(&self, definitions: &[LinkDefinition<'_>]) -> String {
+        let text = self.0;
+        let scan = self.scan();
+        let literal = literal_ranges(text, &scan.code);
+        let mut out = String::new();
+        let mut pos = 0;
+        let mut copied = 0;
+        while pos < text.len() {
+            if let Some(range) = literal.iter().find(|r| r.start == pos) {
+                pos = range.end;
+                continue;
+            }
+            if let Some(def) = scan.definitions.iter().find(|d| d.span.start == pos) {
+                out += &text[copied..pos];
+                pos = def.span.end;
+                copied = pos;
+                continue;
+            }
+            match text.as_bytes()[pos] {
+                b'\\' => pos += 2,
+                b'[' => match reference_link(text, pos, &literal, definitions) {
+                    Some((link, end)) => {
+                        out += &text[copied..pos];
+                        out += &link;
+                        pos = end;
+                        copied = pos;
+                    }
+                    None => pos += 1,
+                },
+                _ => pos += 1,
+            }
+        }
+        out += &text[copied.min(text.len())..];
+        out
+    }
+
+    /// Rewrites link destinations and issue references, leaving code untouched.
+    ///
+    /// `rewrite` is called for the destination of every inline link and image, and for every
+    /// issue reference like `#12` or `GH-12` in the text. If it returns a string, that replaces
+    /// the destination or reference.
+    pub fn rewrite_links(&self, mut rewrite: impl FnMut(Rewrite<'a>) -> Option<String>) -> String {
+        let text = self.0;
+        let literal = literal_ranges(text, &self.scan().code);
+        let bytes = text.as_bytes();
+        let is_word = |pos: usize| bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_';
+        let mut out = String::new();
+        let mut pos = 0;
+        let mut copied = 0;
+        let replace = |out: &mut String, range: Range<usize>, copied: &mut usize, with: &str| {
+            *out += &text[*copied..range.start];
+            *out += with;
+            *copied = range.end;
+        };
+        while pos < text.len() {
+            if let Some(range) = literal.iter().find(|r| r.start == pos) {
+                pos = range.end;
+                continue;
+            }
+            let rest = &text[pos..];
+            let at_word_start = pos == 0 || !(is_word(pos - 1) || bytes[pos - 1] == b'&');
+            // Advances past the first character, which may not be ASCII.
+            let next_char = |pos: usize| pos + text[pos..].chars().next().map_or(1, char::len_utf8);
+            if rest.starts_with('\\') {
+                pos = next_char(pos + 1).min(text.len());
+            } else if at_word_start
+                && (rest.starts_with("http://")
+                    || rest.starts_with("https://")
+                    || rest.starts_with("www."))
+            {
+                // Bare URLs are autolinked, and may contain something like `#12` themselves.
+                pos += rest.find(char::is_whitespace).unwrap_or(rest.len());
+            } else if let Some(tag) = rest.strip_prefix('<') {
+                // Autolinks and HTML tags.
+                match tag[..tag.find('\n').unwrap_or(tag.len())].find('>') {
+                    Some(end) if tag.starts_with(|c: char| c.is_ascii_alphabetic() || c == '/') => {
+                        pos += end + 2
+                    }
+                    _ => pos += 1,
+                }
+            } else if rest.starts_with('[') || rest.starts_with("![") {
+                let open = if rest.starts_with('!') { pos + 1 } else { pos };
+                let inline = closing_bracket(text, open, &literal)
+                    .filter(|&close| text[close + 1..].starts_with('('))
+                    .and_then(|close| Some((close + 1, closing_paren(text, close + 1)?)));
+                let Some((paren, end)) = inline else {
+                    pos = open + 1;
+                    continue;
+                };
+                let inside = &text[paren + 1..end];
+                let leading = inside.len() - inside.trim_start().len();
+                let dest_start = paren + 1 + leading;
+                let dest_end = match text[dest_start..end].strip_prefix('<') {
+                    Some(angled) => dest_start + 1 + angled.find('>').unwrap_or(angled.len()),
+                    None => {
+                        dest_start
+                            + text[dest_start..end]
+                                .find(char::is_whitespace)
+                                .unwrap_or(end - dest_start)
+                    }
+                };
+                let url_start = dest_start + usize::from(text[dest_start..].starts_with('<'));
+                let url = &text[url_start..dest_end];
+                if let Some(new) = rewrite(Rewrite::Destination {
+                    url,
+                    image: open != pos,
+                }) {
+                    replace(&mut out, url_start..dest_end, &mut copied, &new);
+                }
+                pos = end + 1;
+            } else if at_word_start && (rest.starts_with('#') || rest.starts_with("GH-")) {
+                let prefix = if rest.starts_with('#') { 1 } else { 3 };
+                let digits = rest[prefix..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len() - prefix);
+                let end = pos + prefix + digits;
+                let issue = rest[prefix..prefix + digits].parse().ok();
+                match issue {
+                    Some(number) if end == text.len() || !is_word(end) => {
+                        let reference = &text[pos..end];
+                        if let Some(new) = rewrite(Rewrite::Issue { reference, number }) {
+                            replace(&mut out, pos..end, &mut copied, &new);
+                        }
+                        pos = end;
+                    }
+                    _ => pos += prefix,
+                }
+            } else {
+                pos = next_char(pos);
+            }
+        }
+        out += &text[copied.min(text.len())..];
+        out
+    }
+
+    fn scan(&self) -> Scan<'a> {
+        let mut scan = Scan::default();
+        let mut remainder = self.0;
+        // The fence character and length, and the start of the code block we're in.
+        let mut fence = None;
+        let mut in_paragraph = false;
+        // Start of the current paragraph, if it can be turned into a setext heading.
+        let mut paragraph_start = None;
         loop {
-            let contents = section_contents[..section_contents.len() - remainder.len()].trim();
+            let start = self.0.len() - remainder.len();
             let Some(line) = next_line(&mut remainder) else {
-                out.push((section_name, Markdown(contents)));
                 break;
             };
-            if let Some(name) = start_of_section(level, line) {
-                out.push((section_name, Markdown(contents)));
-                section_name = name;
-                section_contents = &self.0[self.0.len() - remainder.len()..];
+            let end = self.0.len() - remainder.len();
+            let (indent, content) = split_indent(line);
+            let content = content.trim_end();
+
+            if let Some((ch, len, block_start)) = fence {
+                if indent < 4 && is_closing_fence(content, ch, len) {
+                    scan.code.push(block_start..end);
+                    fence = None;
+                }
+                continue;
             }
+            if content.is_empty() {
+                in_paragraph = false;
+                paragraph_start = None;
+                continue;
+            }
+            if indent >= 4 {
+                // Either an indented code block, or a continuation line of a paragraph.
+                if !in_paragraph {
+                    match scan.code.last_mut() {
+                        Some(block) if self.0[block.end..start].trim().is_empty() => {
+                            block.end = end
+                        }
+                        _ => scan.code.push(start..end),
+                    }
+                }
+                continue;
+            }
+
+            let heading = if let Some((level, title)) = atx_heading(content) {
+                Some(Heading {
+                    level,
+                    title,
+                    start,
+                    end,
+                })
+            } else if let (Some(par), Some(level)) = (paragraph_start, setext_underline(content)) {
+                Some(Heading {
+                    level,
+                    title: self.0[par..start].trim(),
+                    start: par,
+                    end,
+                })
+            } else {
+                None
+            };
+            if let Some(heading) = heading {
+                scan.headings.push(heading);
+                in_paragraph = false;
+                paragraph_start = None;
+            } else if let Some((ch, len)) = opening_fence(content) {
+                fence = Some((ch, len, start));
+                in_paragraph = false;
+                paragraph_start = None;
+            } else if is_thematic_break(content) {
+                in_paragraph = false;
+                paragraph_start = None;
+            } else if starts_container(content) {
+                // List items and block quotes interrupt paragraphs, and their contents can't be
+                // underlined to become a setext heading.
+                in_paragraph = true;
+                paragraph_start = None;
+            } else if in_paragraph {
+                // Paragraph continuation line.
+            } else if let Some((label, url, title)) = link_definition(content) {
+                // Definitions can't interrupt a paragraph, but can follow each other.
+                scan.definitions.push(LinkDefinition {
+                    label,
+                    url,
+                    title,
+                    span: start..end,
+                });
+            } else {
+                in_paragraph = true;
+                paragraph_start = Some(start);
+            }
+        }
+        if let Some((_, _, block_start)) = fence {
+            // Unclosed code blocks extend to the end of the document.
+            scan.code.push(block_start..self.0.len());
         }
+        scan
+    }
+}
 
-        out
+/// Something that [`Markdown::rewrite_links`] can rewrite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rewrite<'a> {
+    /// The destination of an inline link or image.
+    Destination { url: &'a str, image: bool },
+    /// An issue reference like `#12` or `GH-12`.
+    Issue { reference: &'a str, number: u64 },
+}
+
+/// The block structure of a document, as far as we care about it.
+#[derive(Default)]
+struct Scan<'a> {
+    headings: Vec<Heading<'a>>,
+    definitions: Vec<LinkDefinition<'a>>,
+    /// Byte ranges of fenced and indented code blocks.
+    code: Vec<Range<usize>>,
+}
+
+/// A link reference definition, like `[label]: https://example.com "Title"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkDefinition<'a> {
+    pub label: &'a str,
+    pub url: &'a str,
+    /// The title, including its quotes or parentheses.
+    pub title: Option<&'a str>,
+    /// Byte range of the line containing the definition.
+    pub span: Range<usize>,
+}
+
+impl LinkDefinition<'_> {
+    fn matches(&self, label: &str) -> bool {
+        // Labels are matched case-insensitively, and with whitespace collapsed.
+        let normalize = |s: &str| {
+            s.split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase()
+        };
+        normalize(self.label) == normalize(label)
     }
 }
 
-fn start_of_section(level: u8, mut line: &str) -> Option<&str> {
-    line = line.trim();
+/// A Markdown heading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heading<'a> {
+    /// 1 for `#` and `===`, 2 for `##` and `---`, and so on.
+    pub level: u8,
+    /// The heading text, without `#`s or underline.
+    pub title: &'a str,
+    /// Byte offset of the start of the heading's first line.
+    pub start: usize,
+    /// Byte offset after the heading's last line (including its line terminator).
+    pub end: usize,
+}
+
+/// Returns the end of the blank line starting at `pos`, if `before` ends with a blank line (or is
+/// empty), so that removing text doesn't leave two blank lines in a row. Otherwise, returns `pos`.
+pub fn skip_blank_line(text: &str, pos: usize, before: &str) -> usize {
+    let follows_blank_line = before.is_empty()
+        || before.trim_end_matches([' ', '\t']).ends_with("\n\n")
+        || before.ends_with("\n\r\n");
+    let line_end = text[pos..].find('\n').map_or(text.len(), |i| pos + i + 1);
+    if follows_blank_line && text[pos..line_end].trim().is_empty() {
+        line_end
+    } else {
+        pos
+    }
+}
+
+/// Returns the width of the leading whitespace of `line`, and the rest of the line.
+fn split_indent(line: &str) -> (usize, &str) {
+    let mut width = 0;
+    for (i, c) in line.char_indices() {
+        match c {
+            ' ' => width += 1,
+            // Tabs advance to the next multiple of 4 columns.
+            '\t' => width += 4 - width % 4,
+            _ => return (width, &line[i..]),
+        }
+    }
+    (width, "")
+}
 
-    if !line.starts_with('#') {
+fn atx_heading(content: &str) -> Option<(u8, &str)> {
+    let rest = content.trim_start_matches('#');
+    let level = content.len() - rest.len();
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
         return None;
     }
 
-    let trimmed = line.trim_start_matches('#');
-    let lvl = line.len() - trimmed.len();
-    if lvl == level.into() {
-        Some(trimmed.trim())
+    // An optional closing sequence of `#`s has to be preceded by whitespace.
+    let title = rest.trim();
+    let without_closing = title.trim_end_matches('#');
+    let title = if without_closing.is_empty() {
+        ""
+    } else if without_closing.ends_with([' ', '\t']) {
+        without_closing.trim_end()
+    } else {
+        title
+    };
+    Some((level as u8, title))
+}
+
+fn setext_underline(content: &str) -> Option<u8> {
+    if content.chars().all(|c| c == '=') {
+        Some(1)
+    } else if content.chars().all(|c| c == '-') {
+        Some(2)
     } else {
         None
     }
 }
 
+fn opening_fence(content: &str) -> Option<(char, usize)> {
+    let ch = content.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let info = content.trim_start_matches(ch);
+    let len = content.len() - info.len();
+    // Backtick fences can't have backticks in their info string, so that inline code spans
+    // aren't mistaken for them.
+    if len < 3 || (ch == '`' && info.contains('`')) {
+        return None;
+    }
+    Some((ch, len))
+}
+
+fn is_closing_fence(content: &str, ch: char, len: usize) -> bool {
+    content.len() >= len && content.chars().all(|c| c == ch)
+}
+
+fn is_thematic_break(content: &str) -> bool {
+    let mut chars = content.chars().filter(|c| !matches!(c, ' ' | '\t'));
+    let Some(first @ ('*' | '-' | '_')) = chars.next() else {
+        return false;
+    };
+    let mut count = 1;
+    for c in chars {
+        if c != first {
+            return false;
+        }
+        count += 1;
+    }
+    count >= 3
+}
+
+/// Whether `content` starts a block quote or list item.
+fn starts_container(content: &str) -> bool {
+    if content.starts_with('>') {
+        return true;
+    }
+    let marker_end = match content.find(|c: char| !c.is_ascii_digit()) {
+        Some(0) if content.starts_with(['-', '*', '+']) => 1,
+        Some(n @ 1..=9) if content[n..].starts_with(['.', ')']) => n + 1,
+        _ => return false,
+    };
+    content[marker_end..].is_empty() || content[marker_end..].starts_with([' ', '\t'])
+}
+
+fn link_definition(content: &str) -> Option<(&str, &str, Option<&str>)> {
+    let rest = content.strip_prefix('[')?;
+    let (label, rest) = rest.split_once("]:")?;
+    if label.trim().is_empty() || label.contains(['[', ']']) {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let (url, title) = match rest.strip_prefix('<') {
+        Some(rest) => {
+            let (url, title) = rest.split_once('>')?;
+            (url, title)
+        }
+        None => rest.split_at(rest.find([' ', '\t']).unwrap_or(rest.len())),
+    };
+    let title = title.trim();
+    if url.is_empty() && !rest.starts_with('<') {
+        return None;
+    }
+    if !title.is_empty() && !title.starts_with(['"', '\'', '(']) {
+        return None;
+    }
+    Some((label, url, Some(title).filter(|t| !t.is_empty())))
+}
+
+/// Returns the ranges of `text` that are code blocks or inline code spans, sorted by position.
+fn literal_ranges(text: &str, code_blocks: &[Range<usize>]) -> Vec<Range<usize>> {
+    let mut out = Vec::new();
+    let mut blocks = code_blocks.iter().peekable();
+    let mut pos = 0;
+    while pos < text.len() {
+        if let Some(block) = blocks.next_if(|block| block.start <= pos) {
+            out.push(block.clone());
+            pos = pos.max(block.end);
+            continue;
+        }
+        let limit = blocks.peek().map_or(text.len(), |block| block.start);
+        let Some(tick) = text[pos..limit].find('`') else {
+            pos = limit;
+            continue;
+        };
+
+        // A code span is closed by a backtick string of the same length.
+        let start = pos + tick;
+        let len = text[start..].len() - text[start..].trim_start_matches('`').len();
+        let mut search = start + len;
+        let mut end = None;
+        while let Some(offset) = text[search..limit].find('`') {
+            let run_start = search + offset;
+            let run_len = text[run_start..].len() - text[run_start..].trim_start_matches('`').len();
+            if run_len == len {
+                end = Some(run_start + run_len);
+                break;
+            }
+            search = run_start + run_len;
+        }
+        match end {
+            Some(end) => {
+                out.push(start..end);
+                pos = end;
+            }
+            // An unmatched backtick string is just text.
+            None => pos = start + len,
+        }
+    }
+    out
+}
+
+/// Parses a reference link starting with the `[` at `start`, and returns it as an inline link,
+/// along with the offset where it ends.
+fn reference_link(
+    text: &str,
+    start: usize,
+    literal: &[Range<usize>],
+    definitions: &[LinkDefinition<'_>],
+) -> Option<(String, usize)> {
+    let text_end = closing_bracket(text, start, literal)?;
+    let link_text = &text[start + 1..text_end];
+    let after = &text[text_end + 1..];
+    let (label, end) = if after.starts_with('(') {
+        // Inline link.
+        return None;
+    } else if after.starts_with('[') {
+        let label_end = closing_bracket(text, text_end + 1, literal)?;
+        let label = &text[text_end + 2..label_end];
+        if label.is_empty() {
+            (link_text, label_end + 1)
+        } else {
+            (label, label_end + 1)
+        }
+    } else {
+        (link_text, text_end + 1)
+    };
+
+    let def = definitions.iter().find(|def| def.matches(label))?;
+    let link = match def.title {
+        Some(title) => format!("[{link_text}]({} {title})", def.url),
+        None => format!("[{link_text}]({})", def.url),
+    };
+    Some((link, end))
+}
+
+/// Finds the `)` matching the `(` at `open`.
+fn closing_paren(text: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut pos = open;
+    while pos < text.len() {
+        match text.as_bytes()[pos] {
+            b'\\' => pos += 1,
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(pos);
+                }
+            }
+            b'\n' => return None,
+            _ => {}
+        }
+        pos += 1;
+    }
+    None
+}
+
+/// Finds the `]` matching the `[` at `open`, skipping code spans and escaped brackets.
+fn closing_bracket(text: &str, open: usize, literal: &[Range<usize>]) -> Option<usize> {
+    let mut depth = 0;
+    let mut pos = open;
+    while pos < text.len() {
+        if let Some(range) = literal.iter().find(|r| r.start == pos) {
+            pos = range.end;
+            continue;
+        }
+        match text.as_bytes()[pos] {
+            b'\\' => pos += 1,
+            b'[' => depth += 1,
+            b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(pos);
+                }
+            }
+            // Links can't span paragraphs.
+            b'\n'
+                if text[pos + 1..]
+                    .trim_start_matches([' ', '\t', '\r'])
+                    .starts_with('\n') =>
+            {
+                return None;
+            }
+            _ => {}
+        }
+        pos += 1;
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use expect_test::expect;
@@ -70,17 +675,17 @@ mod tests {
     #[test]
     fn test_sections() {
         let markdown = Markdown(
-            r#"
-                # 1
-                bla
-                ## 2.1
-                ## 2.2
-                aa
-                ## 2.3
-
-                - a
-                - b
-            "#,
+            "
+# 1
+bla
+## 2.1
+## 2.2
+aa
+## 2.3
+
+- a
+- b
+",
         );
         let sections = markdown.sections(2);
 
@@ -96,7 +701,7 @@ mod tests {
                 ),
                 (
                     "2.3",
-                    "- a\n                - b",
+                    "- a\n- b",
                 ),
             ]
         "#]]
@@ -108,4 +713,143 @@ mod tests {
         "#]]
         .assert_debug_eq(&sections);
     }
+
+    #[test]
+    fn test_headings() {
+        #[track_caller]
+        fn check(input: &str, expect: expect_test::Expect) {
+            let headings = Markdown(input)
+                .headings()
+                .iter()
+                .map(|h| format!("{} {:?}\n", h.level, h.title))
+                .collect::<String>();
+            expect.assert_eq(&headings);
+        }
+
+        check(
+            "# a\n##b\n### c ###\n#### d#\n####### e\n    # f\n   # g\n#\n## #",
+            expect![[r#"
+                1 "a"
+                3 "c"
+                4 "d#"
+                1 "g"
+                1 ""
+                2 ""
+            "#]],
+        );
+        check(
+            "# a\n\n```rust\n#[derive(Debug)]\n# hidden\n```\n\n~~~~\n```\n# b\n~~~~\n## c",
+            expect![[r#"
+                1 "a"
+                2 "c"
+            "#]],
+        );
+        check(
+            "text\n\n    # not a heading\n    ```\n# a",
+            expect![[r#"
+                1 "a"
+            "#]],
+        );
+        check(
+            "Title\n=====\n\nSub\ntitle\n---\n\n- item\n---\n\n---\ntext `inline ``` span`\n--",
+            expect![[r#"
+                1 "Title"
+                2 "Sub\ntitle"
+                2 "text `inline ``` span`"
+            "#]],
+        );
+    }
+
+    #[test]
+    fn test_remove_html_comments() {
+        let input = "- Added a thing.<!-- see #12 -->\n\n<!--\nReview notes.\n-->\n\n- Fixed `<!-- x -->`.\n\n```html\n<!-- example -->\n```\n\n<!-- unclosed";
+        expect![[r#"
+            - Added a thing.
+
+            - Fixed `<!-- x -->`.
+
+            ```html
+            <!-- example -->
+            ```
+
+        "#]]
+        .assert_eq(&Markdown(input).remove_html_comments());
+    }
+
+    #[test]
+    fn test_relevel_headings() {
+        let input =
+            "Fixed\nbugs\n=====\n\n### Details ####\n\n```\n# comment\n```\n\n###### Deep\n";
+        expect![[r#"
+            ## Fixed bugs
+
+            #### Details
+
+            ```
+            # comment
+            ```
+
+            ###### Deep
+        "#]]
+        .assert_eq(&Markdown(input).relevel_headings(2));
+        let input = "## Added\n\n- thing\n";
+        assert_eq!(Markdown(input).relevel_headings(2), input);
+    }
+
+    #[test]
+    fn test_resolve_reference_links() {
+        let changelog = Markdown(
+            r#"## [1.1.0]
+
+- Fixes [#12] and [the bug][bug], see [1.1.0][] and [`code`][bug].
+- `[bug]` and [not defined] stay, as does [inline](https://example.com).
+
+```
+[bug]
+```
+
+[1.1.0]: https://example.com/compare/v1.0.0...v1.1.0
+[#12]: <https://example.com/issues/12> "Issue 12"
+[BUG]: https://example.com/bug
+"#,
+        );
+        let definitions = changelog.link_definitions();
+        expect![[r##"
+            [
+                LinkDefinition {
+                    label: "1.1.0",
+                    url: "https://example.com/compare/v1.0.0...v1.1.0",
+                    title: None,
+                    span: 168..221,
+                },
+                LinkDefinition {
+                    label: "#12",
+                    url: "https://example.com/issues/12",
+                    title: Some(
+                        "\"Issue 12\"",
+                    ),
+                    span: 221..271,
+                },
+                LinkDefinition {
+                    label: "BUG",
+                    url: "https://example.com/bug",
+                    title: None,
+                    span: 271..302,
+                },
+            ]
+        "##]]
+        .assert_debug_eq(&definitions);
+
+        let (_, notes) = &changelog.sections(2)[0];
+        expect![[r#"
+            - Fixes [#12](https://example.com/issues/12 "Issue 12") and [the bug](https://example.com/bug), see [1.1.0](https://example.com/compare/v1.0.0...v1.1.0) and [`code`](https://example.com/bug).
+            - `[bug]` and [not defined] stay, as does [inline](https://example.com).
+
+            ```
+            [bug]
+            ```
+
+        "#]]
+        .assert_eq(&notes.resolve_reference_links(&definitions));
+    }
 }
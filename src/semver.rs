@@ -0,0 +1,223 @@
+//! Semantic versions, as used by Cargo.
+//!
+//! See <https://semver.org/>.
+
+use std::{cmp::Ordering, fmt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// Pre-release identifiers, like `beta.1` (without the leading `-`).
+    pub pre: String,
+    /// Build metadata, like `git.abcdef` (without the leading `+`).
+    pub build: String,
+}
+
+impl Version {
+    /// Parses a complete version like `1.2.3-rc.1+build`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (rest, build) = match s.split_once('+') {
+            Some((rest, build)) => (rest, Some(build)),
+            None => (s, None),
+        };
+        let (core, pre) = match rest.split_once('-') {
+            Some((core, pre)) => (core, Some(pre)),
+            None => (rest, None),
+        };
+
+        let mut parts = core.split('.').map(parse_number);
+        let (Some(Some(major)), Some(Some(minor)), Some(Some(patch)), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return None;
+        };
+
+        let valid_identifiers = |s: &str| {
+            s.split('.').all(|ident| {
+                !ident.is_empty() && ident.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            })
+        };
+        if !pre.is_none_or(valid_identifiers) || !build.is_none_or(valid_identifiers) {
+            return None;
+        }
+
+        Some(Self {
+            major,
+            minor,
+            patch,
+            pre: pre.unwrap_or_default().to_string(),
+            build: build.unwrap_or_default().to_string(),
+        })
+    }
+
+    /// Finds all versions in `text`, like those in a changelog heading.
+    ///
+    /// Versions may be prefixed with a `v` or a tag prefix like `name-v`, and enclosed in
+    /// brackets or parentheses, so that `v1.0.0`, `[1.0.0]`, `(v1.0.0):` and `foo-v1.0.0` are all
+    /// recognized, as are Markdown links like `[1.0.0](https://...)`. Dates like `2024-01-01` are
+    /// not mistaken for versions.
+    pub fn find_all(text: &str) -> Vec<Self> {
+        text.split_whitespace()
+            .filter_map(|word| {
+                // The target of a link may contain other versions, like in a compare URL.
+                let word = word.split_once("](").map_or(word, |(text, _)| text);
+                let word = word
+                    .trim_start_matches(['[', '('])
+                    .trim_end_matches([']', ')', ':', ',']);
+                let version = word.strip_prefix(['v', 'V']).unwrap_or(word);
+                Self::parse(version).or_else(|| {
+                    let (_, version) = word.rsplit_once("-v")?;
+                    Self::parse(version)
+                })
+            })
+            .collect()
+    }
+}
+
+fn parse_number(s: &str) -> Option<u64> {
+    // Leading zeros are not allowed.
+    if s.is_empty() || (s.len() > 1 && s.starts_with('0')) {
+        return None;
+    }
+    if !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (self.pre.is_empty(), other.pre.is_empty()) {
+                // A pre-release has lower precedence than the release itself.
+                (true, true) => Ordering::Equal,
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => cmp_pre(&self.pre, &other.pre),
+            })
+            // Build metadata has no precedence, but we still need a total order that's consistent
+            // with `Eq`.
+            .then_with(|| self.build.cmp(&other.build))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn cmp_pre(a: &str, b: &str) -> Ordering {
+    let mut a = a.split('.');
+    let mut b = b.split('.');
+    loop {
+        let ord = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            // Numeric identifiers compare numerically and sort before alphanumeric ones.
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => a.cmp(b),
+            },
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.pre.is_empty() {
+            write!(f, "-{}", self.pre)?;
+        }
+        if !self.build.is_empty() {
+            write!(f, "+{}", self.build)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        #[track_caller]
+        fn check(s: &str, expect: Option<&str>) {
+            assert_eq!(
+                Version::parse(s).map(|v| v.to_string()).as_deref(),
+                expect,
+                "{s}"
+            );
+        }
+
+        check("1.2.3", Some("1.2.3"));
+        check("0.1.34", Some("0.1.34"));
+        check("1.0.0-beta.1", Some("1.0.0-beta.1"));
+        check("1.0.0-rc.1+build.5", Some("1.0.0-rc.1+build.5"));
+        check("1.0.0+20240101", Some("1.0.0+20240101"));
+        check("1.0", None);
+        check("1.0.0.0", None);
+        check("01.0.0", None);
+        check("1.0.0-", None);
+        check("1.0.0-a..b", None);
+        check("2024-01-01", None);
+        check("v1.0.0", None);
+    }
+
+    #[test]
+    fn test_find_all() {
+        #[track_caller]
+        fn check(text: &str, expect: &[&str]) {
+            let found = Version::find_all(text)
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>();
+            assert_eq!(found, expect, "{text}");
+        }
+
+        check("v0.1.34", &["0.1.34"]);
+        check("Subpackage v1.0.0", &["1.0.0"]);
+        check("[1.2.0] - 2026-01-01", &["1.2.0"]);
+        check("[v1.2.0-beta.1]: 2026-01-01", &["1.2.0-beta.1"]);
+        check("1.0.0 (2026-01-01)", &["1.0.0"]);
+        check("Unreleased", &[]);
+        check("Version 1.0", &[]);
+        check(
+            "[1.2.0](https://github.com/o/r/compare/v1.1.0...v1.2.0) (2024-01-01)",
+            &["1.2.0"],
+        );
+        check("foo-v1.2.0", &["1.2.0"]);
+        check("foo-vec-v1.2.0-rc.1", &["1.2.0-rc.1"]);
+        check("1.0.0-v2", &["1.0.0-v2"]);
+    }
+
+    #[test]
+    fn test_ordering() {
+        let versions = [
+            "0.1.0",
+            "0.1.3",
+            "0.1.34",
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+            "1.0.0-alpha.beta",
+            "1.0.0-beta.2",
+            "1.0.0-beta.11",
+            "1.0.0-rc.1",
+            "1.0.0",
+        ]
+        .map(|v| Version::parse(v).unwrap());
+        for pair in versions.windows(2) {
+            assert!(pair[0] < pair[1], "{} < {}", pair[0], pair[1]);
+        }
+    }
+}
@@ -1,10 +1,19 @@
 //! Test support.
 
-use std::{cell::RefCell, env, path::PathBuf};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 use expect_test::{expect, Expect};
 
-use crate::{Params, Pipeline, Workspace};
+use crate::{
+    assemble_fragments, changelog, changelog_command, command, run_workspaces, Package, Params,
+    Workspace,
+};
 
 #[allow(unused_macros)]
 macro_rules! print {
@@ -39,7 +48,7 @@ macro_rules! eprintln {
 }
 
 thread_local! {
-    static OUTPUT: RefCell<String> = RefCell::new(String::new());
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
 }
 
 pub fn append_stdout(s: String) {
@@ -77,12 +86,16 @@ impl Params {
             args: String::new(),
             crates_io_token: Some("dummy-token".into()),
             github_token: Some("github-dummy-token".into()),
+            registry_tokens: BTreeMap::new(),
+            attachments: BTreeMap::new(),
             commit: test_commit.clone(),
             cargo_doc_flags: String::new(),
             check_only: false,
             skip_docs: false,
             sudo: false,
             no_publish: false,
+            commit_notes: false,
+            workspaces: Vec::new(),
             mock_output: Some(vec![
                 ("git status --porcelain", "".into()),
                 ("git rev-parse HEAD", test_commit),
@@ -108,10 +121,89 @@ impl Params {
         self
     }
 
+    fn with_commit_notes(mut self, git_log_cmd: &'static str, log: &str) -> Self {
+        self.commit_notes = true;
+        self.mock_output
+            .as_mut()
+            .unwrap()
+            .push((git_log_cmd, log.into()));
+        self
+    }
+
+    /// Sets the workspace roots, and repeats the mocked commands for each workspace.
+    fn with_workspaces(mut self, roots: &[&str]) -> Self {
+        self.workspaces = roots.iter().map(PathBuf::from).collect();
+        let mocks = self.mock_output.as_mut().unwrap();
+        *mocks = (0..roots.len()).flat_map(|_| mocks.clone()).collect();
+        self
+    }
+
     fn with_sudo(mut self) -> Self {
         self.sudo = true;
         self
     }
+
+    fn with_attachments(mut self, package: &str, files: &str) -> Self {
+        self.attachments.insert(package.into(), files.into());
+        self
+    }
+
+    fn with_registry_token(mut self, name: &str) -> Self {
+        self.registry_tokens
+            .insert(name.into(), "registry-dummy-token".into());
+        self
+    }
+}
+
+/// Copies a test project into a temporary directory, so that tests can modify it.
+fn copy_test_dir(subdir: &str, name: &str) -> PathBuf {
+    fn copy(from: &Path, to: &Path) {
+        fs::create_dir_all(to).unwrap();
+        for entry in fs::read_dir(from).unwrap() {
+            let entry = entry.unwrap();
+            let to = to.join(entry.file_name());
+            if entry.file_type().unwrap().is_dir() {
+                copy(&entry.path(), &to);
+            } else {
+                fs::copy(entry.path(), to).unwrap();
+            }
+        }
+    }
+
+    let dir = env::temp_dir().join(format!("sludge-cicd-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    copy(&test_dir(subdir), &dir);
+    dir
+}
+
+/// Lists the files in `dir`, and the contents of the changelogs among them.
+fn describe_dir(dir: &Path) -> String {
+    fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                walk(&path, files);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    walk(dir, &mut files);
+    files.sort();
+    let mut out = String::new();
+    for file in &files {
+        out += &format!("{}\n", file.strip_prefix(dir).unwrap().display());
+    }
+    for file in files.iter().filter(|file| file.ends_with("CHANGELOG.md")) {
+        let contents = fs::read_to_string(file).unwrap();
+        out += &format!(
+            "\n--- {} ---\n{contents}",
+            file.strip_prefix(dir).unwrap().display()
+        );
+    }
+    out.replace(&changelog::today().to_string(), "<DATE>")
 }
 
 fn redact(s: &str) -> String {
@@ -121,7 +213,7 @@ fn redact(s: &str) -> String {
 fn check_output(params: Params, expect: Expect) {
     OUTPUT.replace(String::new());
 
-    Pipeline::new(params).unwrap().run().unwrap();
+    run_workspaces(params).unwrap();
 
     let output = redact(&OUTPUT.take());
     expect.assert_eq(&output);
@@ -129,10 +221,7 @@ fn check_output(params: Params, expect: Expect) {
 fn check_error(params: Params, expected_error: Expect, expected_output: Expect) {
     OUTPUT.replace(String::new());
 
-    let error = match Pipeline::new(params) {
-        Ok(pipe) => pipe.run().unwrap_err(),
-        Err(e) => e,
-    };
+    let error = run_workspaces(params).unwrap_err();
 
     let mut string = redact(&error.to_string());
     if !string.ends_with('\n') {
@@ -185,7 +274,11 @@ fn missing_metadata() {
     check_error(
         Params::test("no-license"),
         expect![[r#"
-            package `mypkg` is missing a license or license-file field
+            error: package `mypkg` is missing a license or license-file field
+             --> sludge-cicd-test-projects/no-license/Cargo.toml:1:2
+              |
+            1 | [package]
+              |  ^^^^^^^
         "#]],
         expect![[r#"
             ::group::INIT
@@ -209,7 +302,151 @@ fn missing_metadata() {
     check_error(
         Params::test("no-description"),
         expect![[r#"
-            package `mypkg` is missing a description field
+            error: package `mypkg` is missing a description field
+             --> sludge-cicd-test-projects/no-description/Cargo.toml:1:2
+              |
+            1 | [package]
+              |  ^^^^^^^
+        "#]],
+        expect![[r#"
+            ::group::INIT
+            publishable packages in workspace: [mypkg@1.2.3]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+#[test]
+fn workspace_missing_field() {
+    check_error(
+        Params::test("workspace-missing-field"),
+        expect![[r#"
+            error: package `pkg` inherits `description`, but the workspace does not define `workspace.package.description`
+             --> sludge-cicd-test-projects/workspace-missing-field/pkg/Cargo.toml:5:13
+              |
+            5 | description.workspace = true
+              |             ^^^^^^^^^
+        "#]],
+        expect![[r#"
+            ::group::INIT
+            INIT: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+#[test]
+fn path_dep_without_version() {
+    check_error(
+        Params::test("path-dep-without-version"),
+        expect![[r#"
+            error: package `mypkg` depends on `dep` by path only, which can't be published without a `version`
+             --> sludge-cicd-test-projects/path-dep-without-version/Cargo.toml:8:1
+              |
+            8 | dep = { path = "dep" }
+              | ^^^
+        "#]],
+        expect![[r#"
+            ::group::INIT
+            mypkg@1.0.0 depends on dep@1.0.0
+            publishable packages in workspace: [dep@1.0.0, mypkg@1.0.0]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+#[test]
+fn invalid_metadata() {
+    check_error(
+        Params::test("too-many-keywords"),
+        expect![[r#"
+            error: package `mypkg` has 6 keywords, but crates.io allows at most 5
+             --> sludge-cicd-test-projects/too-many-keywords/Cargo.toml:7:1
+              |
+            7 | keywords = ["one", "two", "three", "four", "five", "six"]
+              | ^^^^^^^^
+        "#]],
+        expect![[r#"
+            ::group::INIT
+            publishable packages in workspace: [mypkg@1.2.3]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+    check_error(
+        Params::test("old-rust-version"),
+        expect![[r#"
+            error: package `mypkg` has `rust-version` 1.50, but edition 2021 requires at least 1.56
+             --> sludge-cicd-test-projects/old-rust-version/Cargo.toml:8:1
+              |
+            8 | rust-version = "1.50"
+              | ^^^^^^^^^^^^
+        "#]],
+        expect![[r#"
+            ::group::INIT
+            publishable packages in workspace: [mypkg@1.2.3]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+    check_error(
+        Params::test("unquoted-edition"),
+        expect![[r#"
+            error: `edition` of package `mypkg` must be a string, like `edition = "2021"`
+             --> sludge-cicd-test-projects/unquoted-edition/Cargo.toml:6:1
+              |
+            6 | edition = 2021
+              | ^^^^^^^
         "#]],
         expect![[r#"
             ::group::INIT
@@ -496,31 +733,14 @@ fn single_package_sudo() {
 }
 
 #[test]
-fn workspace_inheritance() {
-    check_find_packages(
-        "workspace-inheritance",
-        expect![[r#"
-            [
-                version-normal@4.5.6,
-                version-workspace@555.222.333,
-            ]
-        "#]],
-    );
-    check_find_packages(
-        "workspace-inheritance2",
-        expect![[r#"
-            [
-                version-normal@4.5.6,
-                version-workspace@555.222.333,
-            ]
-        "#]],
-    );
-
+fn cargo_config_registry() {
     check_output(
-        Params::test("workspace-inheritance"),
+        Params::test("cargo-config-registry"),
         expect![[r#"
             ::group::INIT
-            publishable packages in workspace: [version-normal@4.5.6, version-workspace@555.222.333]
+            publishable packages in workspace: [mypkg@1.0.0]
+            mypkg@1.0.0 is published to `my-registry`
+            ::warning::Cargo config defines an alias for `cargo test`, which Cargo ignores in favor of the built-in command
             INIT: 0.00ns
             ::endgroup::
             ::group::BUILD
@@ -537,23 +757,19 @@ fn workspace_inheritance() {
             ::endgroup::
             ::group::PUBLISH
             existing git tags: []
-            2 packages need publishing: [version-normal@4.5.6, version-workspace@555.222.333]
-            publishing version-normal@4.5.6
-            > cargo publish --no-verify -p version-normal --token dummy-token
-            publishing version-workspace@555.222.333
-            > cargo publish --no-verify -p version-workspace --token dummy-token
-            > git tag version-normal-v4.5.6
-            > git tag version-workspace-v555.222.333
-            > git push --tags
+            1 package needs publishing: [mypkg@1.0.0]
+            no `CARGO_REGISTRIES_MY_REGISTRY_TOKEN` set, skipping autopublish step
             PUBLISH: 0.00ns
             ::endgroup::
         "#]],
     );
     check_output(
-        Params::test("workspace-inheritance2"),
+        Params::test("cargo-config-registry").with_registry_token("MY_REGISTRY"),
         expect![[r#"
             ::group::INIT
-            publishable packages in workspace: [version-normal@4.5.6, version-workspace@555.222.333]
+            publishable packages in workspace: [mypkg@1.0.0]
+            mypkg@1.0.0 is published to `my-registry`
+            ::warning::Cargo config defines an alias for `cargo test`, which Cargo ignores in favor of the built-in command
             INIT: 0.00ns
             ::endgroup::
             ::group::BUILD
@@ -570,13 +786,10 @@ fn workspace_inheritance() {
             ::endgroup::
             ::group::PUBLISH
             existing git tags: []
-            2 packages need publishing: [version-normal@4.5.6, version-workspace@555.222.333]
-            publishing version-normal@4.5.6
-            > cargo publish --no-verify -p version-normal --token dummy-token
-            publishing version-workspace@555.222.333
-            > cargo publish --no-verify -p version-workspace --token dummy-token
-            > git tag version-normal-v4.5.6
-            > git tag version-workspace-v555.222.333
+            1 package needs publishing: [mypkg@1.0.0]
+            publishing mypkg@1.0.0 to registry `my-registry`
+            > cargo publish --no-verify -p mypkg --registry my-registry --token registry-dummy-token
+            > git tag v1.0.0
             > git push --tags
             PUBLISH: 0.00ns
             ::endgroup::
@@ -585,82 +798,520 @@ fn workspace_inheritance() {
 }
 
 #[test]
-fn synced_derive() {
+fn workspace_members() {
+    // `crates/experimental` and `vendor/patched` are excluded, `helper` is a member because `cli`
+    // depends on it, and `examples/demo` isn't a member.
     check_find_packages(
+        "workspace-members",
+        expect![[r#"
+            [
+                macros@0.1.0,
+                core@0.1.0,
+                helper@0.1.0,
+                cli@0.1.0,
+            ]
+        "#]],
+    );
+    check_error(
+        Params::test("workspace-default-members"),
+        expect![[r#"
+            package `b` is listed in `workspace.default-members`, but is not a member of the workspace
+        "#]],
+        expect![[r#"
+            ::group::INIT
+            INIT: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+fn cargo_metadata(subdir: &str) -> String {
+    let output = Command::new(env!("CARGO"))
+        .args([
+            "metadata",
+            "--format-version",
+            "1",
+            "--no-deps",
+            "--offline",
+        ])
+        .current_dir(test_dir(subdir))
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{subdir}: {stderr}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Makes sure that reading the manifests ourselves (like the tests do) finds the same packages as
+/// `cargo metadata`.
+#[test]
+fn cargo_metadata_matches_manifests() {
+    fn describe(packages: &[Package]) -> Vec<String> {
+        packages
+            .iter()
+            .map(|package| {
+                let mut deps = package
+                    .dependencies
+                    .iter()
+                    .map(|dep| format!("{dep:?}"))
+                    .collect::<Vec<_>>();
+                deps.sort();
+                let fields = ["description", "license", "repository", "rust-version"]
+                    .map(|field| package.fields.get(field).and_then(|value| value.as_str()));
+                format!(
+                    "{package} {} {fields:?} {deps:#?}",
+                    package.manifest_path.display()
+                )
+            })
+            .collect()
+    }
+
+    for subdir in [
+        "graph",
+        "package-exclude",
+        "package-exclude-root-package",
+        "publish-registries",
+        "path-dep-without-version",
+        "release-notes-links",
+        "single-package-nonpublish",
         "synced-derive",
+        "workspace-inheritance",
+        "workspace-members",
+    ] {
+        let workspace = Workspace::get(test_dir(subdir)).unwrap();
+        let metadata = cargo_metadata(subdir);
+        assert_eq!(
+            describe(&workspace.packages_from_metadata(&metadata, None).unwrap()),
+            describe(&workspace.find_packages().unwrap()),
+            "{subdir}",
+        );
+    }
+}
+
+#[test]
+fn package_exclude() {
+    check_find_packages(
+        "package-exclude",
         expect![[r#"
             [
-                mylib-derive@0.1.2,
-                mylib@0.1.2,
+                a@0.1.0,
+            ]
+        "#]],
+    );
+
+    // A root package can have the setting in its own metadata.
+    check_find_packages(
+        "package-exclude-root-package",
+        expect![[r#"
+            [
+                app@0.1.0,
             ]
         "#]],
     );
 
+    // Packages whose manifest isn't tracked by git are left out too.
+    let workspace = Workspace::get(test_dir("workspace-members")).unwrap();
+    let tracked = [
+        "Cargo.toml",
+        "crates/core/Cargo.toml",
+        "crates/macros/Cargo.toml",
+        "tools/cli/Cargo.toml",
+    ]
+    .map(Path::new)
+    .into();
+    let metadata = cargo_metadata("workspace-members");
+    let packages = workspace
+        .packages_from_metadata(&metadata, Some(&tracked))
+        .unwrap();
+    expect![[r#"
+        [
+            macros@0.1.0,
+            core@0.1.0,
+            cli@0.1.0,
+        ]
+    "#]]
+    .assert_debug_eq(&packages);
+}
+
+#[test]
+fn multiple_workspaces() {
+    // A `v1.0.0` tag is ambiguous with several workspaces, so it doesn't count as a release of
+    // `tool`. The attachments of `tool` are skipped while publishing the `crates` workspace.
     check_output(
-        Params::test("synced-derive"),
+        Params::test("multiple-workspaces")
+            .with_tags(&["a-v0.2.0", "v1.0.0"])
+            .with_workspaces(&["crates", "tools"])
+            .with_attachments(
+                "tool",
+                "sludge-cicd-test-projects/multiple-workspaces/tools/Cargo.toml",
+            ),
         expect![[r#"
             ::group::INIT
-            mylib@0.1.2 depends on mylib-derive@0.1.2
-            publishable packages in workspace: [mylib-derive@0.1.2, mylib@0.1.2]
+            workspace: crates
+            publishable packages in workspace: [a@0.2.0, b@0.2.0]
             INIT: 0.00ns
             ::endgroup::
             ::group::BUILD
-            > cargo test --workspace --no-run
+            > cd crates && cargo test --workspace --no-run
             BUILD: 0.00ns
             ::endgroup::
             ::group::BUILD_DOCS
-            > cargo doc --workspace
+            > cd crates && cargo doc --workspace
             BUILD_DOCS: 0.00ns
             ::endgroup::
             ::group::TEST
-            > cargo test --workspace
+            > cd crates && cargo test --workspace
             TEST: 0.00ns
             ::endgroup::
             ::group::PUBLISH
-            existing git tags: []
-            2 packages need publishing: [mylib-derive@0.1.2, mylib@0.1.2]
-            publishing mylib-derive@0.1.2
-            > cargo publish --no-verify -p mylib-derive --token dummy-token
-            publishing mylib@0.1.2
-            > cargo publish --no-verify -p mylib --token dummy-token
-            > git tag v0.1.2
+            existing git tags: ["a-v0.2.0"]
+            1 package needs publishing: [b@0.2.0]
+            publishing b@0.2.0
+            > cd crates && cargo publish --no-verify -p b --token dummy-token
+            > git tag b-v0.2.0
             > git push --tags
+            > gh release create b-v0.2.0 --notes-file - <<<EOF
+            - Made `a` and `b` faster.
+            EOF
             PUBLISH: 0.00ns
             ::endgroup::
-        "#]],
-    );
-    check_output(
-        Params::test("synced-derive").with_tags(&["v0.1.2"]),
-        expect![[r#"
             ::group::INIT
-            mylib@0.1.2 depends on mylib-derive@0.1.2
-            publishable packages in workspace: [mylib-derive@0.1.2, mylib@0.1.2]
+            workspace: tools
+            publishable packages in workspace: [tool@1.0.0]
             INIT: 0.00ns
             ::endgroup::
             ::group::BUILD
-            > cargo test --workspace --no-run
+            > cd tools && cargo test --workspace --no-run
             BUILD: 0.00ns
             ::endgroup::
             ::group::BUILD_DOCS
-            > cargo doc --workspace
+            > cd tools && cargo doc --workspace
             BUILD_DOCS: 0.00ns
             ::endgroup::
             ::group::TEST
-            > cargo test --workspace
+            > cd tools && cargo test --workspace
             TEST: 0.00ns
             ::endgroup::
             ::group::PUBLISH
-            existing git tags: ["v0.1.2"]
-            no packages need publishing, done
+            existing git tags: ["a-v0.2.0"]
+            package 'tool' has 1 release attachments: ["sludge-cicd-test-projects/multiple-workspaces/tools/Cargo.toml"]
+            1 package needs publishing: [tool@1.0.0]
+            publishing tool@1.0.0
+            > cd tools && cargo publish --no-verify -p tool --token dummy-token
+            > git tag tool-v1.0.0
+            > git push --tags
+            > gh release create tool-v1.0.0 --notes-file - <<<EOF
+            - Stabilized the command line interface.
+            EOF
+            > gh release upload tool-v1.0.0 sludge-cicd-test-projects/multiple-workspaces/tools/Cargo.toml
             PUBLISH: 0.00ns
             ::endgroup::
+            ::group::SUMMARY
+            crates: published [b@0.2.0]
+            tools: published [tool@1.0.0]
+            SUMMARY: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+
+    // Attachments of a package that isn't in any workspace are an error.
+    check_error(
+        Params::test("multiple-workspaces")
+            .with_workspaces(&["crates", "tools"])
+            .with_attachments("c", "Cargo.toml"),
+        expect![[r#"
+            `ATTACHMENTS_c` is set, but package c doesn't exist in any workspace
         "#]],
+        expect![[""]],
     );
+}
+
+#[test]
+fn publish_registries() {
     check_output(
-        Params::test("synced-derive").with_tags(&["mylib-v0.1.2"]),
+        Params::test("publish-registries").with_registry_token("INTERNAL"),
         expect![[r#"
             ::group::INIT
-            mylib@0.1.2 depends on mylib-derive@0.1.2
+            publishable packages in workspace: [both@0.1.0, internal@0.1.0, public@0.1.0]
+            both@0.1.0 is published to `crates-io`, `internal`
+            internal@0.1.0 is published to `internal`
+            public@0.1.0 is published to `crates-io`
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: []
+            3 packages need publishing: [both@0.1.0, internal@0.1.0, public@0.1.0]
+            publishing both@0.1.0
+            > cargo publish --no-verify -p both --token dummy-token
+            publishing both@0.1.0 to registry `internal`
+            > cargo publish --no-verify -p both --registry internal --token registry-dummy-token
+            publishing internal@0.1.0 to registry `internal`
+            > cargo publish --no-verify -p internal --registry internal --token registry-dummy-token
+            publishing public@0.1.0
+            > cargo publish --no-verify -p public --token dummy-token
+            > git tag v0.1.0
+            > git push --tags
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+    // Without a crates.io token, nothing is published, since `public` and `both` need it.
+    let mut params = Params::test("publish-registries").with_registry_token("INTERNAL");
+    params.crates_io_token = None;
+    check_output(
+        params,
+        expect![[r#"
+            ::group::INIT
+            publishable packages in workspace: [both@0.1.0, internal@0.1.0, public@0.1.0]
+            both@0.1.0 is published to `crates-io`, `internal`
+            internal@0.1.0 is published to `internal`
+            public@0.1.0 is published to `crates-io`
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: []
+            3 packages need publishing: [both@0.1.0, internal@0.1.0, public@0.1.0]
+            no `CRATES_IO_TOKEN` set, skipping autopublish step
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+#[test]
+fn workspace_inheritance() {
+    check_find_packages(
+        "workspace-inheritance",
+        expect![[r#"
+            [
+                version-normal@4.5.6,
+                version-workspace@555.222.333,
+            ]
+        "#]],
+    );
+    check_find_packages(
+        "workspace-inheritance2",
+        expect![[r#"
+            [
+                version-normal@4.5.6,
+                version-workspace@555.222.333,
+            ]
+        "#]],
+    );
+
+    check_output(
+        Params::test("workspace-inheritance"),
+        expect![[r#"
+            ::group::INIT
+            publishable packages in workspace: [version-normal@4.5.6, version-workspace@555.222.333]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: []
+            2 packages need publishing: [version-normal@4.5.6, version-workspace@555.222.333]
+            publishing version-normal@4.5.6
+            > cargo publish --no-verify -p version-normal --token dummy-token
+            publishing version-workspace@555.222.333
+            > cargo publish --no-verify -p version-workspace --token dummy-token
+            > git tag version-normal-v4.5.6
+            > git tag version-workspace-v555.222.333
+            > git push --tags
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+    check_output(
+        Params::test("workspace-inheritance2"),
+        expect![[r#"
+            ::group::INIT
+            publishable packages in workspace: [version-normal@4.5.6, version-workspace@555.222.333]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: []
+            2 packages need publishing: [version-normal@4.5.6, version-workspace@555.222.333]
+            publishing version-normal@4.5.6
+            > cargo publish --no-verify -p version-normal --token dummy-token
+            publishing version-workspace@555.222.333
+            > cargo publish --no-verify -p version-workspace --token dummy-token
+            > git tag version-normal-v4.5.6
+            > git tag version-workspace-v555.222.333
+            > git push --tags
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+#[test]
+fn synced_derive() {
+    check_find_packages(
+        "synced-derive",
+        expect![[r#"
+            [
+                mylib-derive@0.1.2,
+                mylib@0.1.2,
+            ]
+        "#]],
+    );
+
+    check_output(
+        Params::test("synced-derive"),
+        expect![[r#"
+            ::group::INIT
+            mylib@0.1.2 depends on mylib-derive@0.1.2
+            publishable packages in workspace: [mylib-derive@0.1.2, mylib@0.1.2]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: []
+            2 packages need publishing: [mylib-derive@0.1.2, mylib@0.1.2]
+            publishing mylib-derive@0.1.2
+            > cargo publish --no-verify -p mylib-derive --token dummy-token
+            publishing mylib@0.1.2
+            > cargo publish --no-verify -p mylib --token dummy-token
+            > git tag v0.1.2
+            > git push --tags
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+    check_output(
+        Params::test("synced-derive").with_tags(&["v0.1.2"]),
+        expect![[r#"
+            ::group::INIT
+            mylib@0.1.2 depends on mylib-derive@0.1.2
+            publishable packages in workspace: [mylib-derive@0.1.2, mylib@0.1.2]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: ["v0.1.2"]
+            no packages need publishing, done
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+    check_output(
+        Params::test("synced-derive").with_tags(&["mylib-v0.1.2"]),
+        expect![[r#"
+            ::group::INIT
+            mylib@0.1.2 depends on mylib-derive@0.1.2
+            publishable packages in workspace: [mylib-derive@0.1.2, mylib@0.1.2]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: ["mylib-v0.1.2"]
+            1 package needs publishing: [mylib-derive@0.1.2]
+            publishing mylib-derive@0.1.2
+            > cargo publish --no-verify -p mylib-derive --token dummy-token
+            > git tag mylib-derive-v0.1.2
+            > git push --tags
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+#[test]
+fn commit_release_notes() {
+    check_output(
+        Params::test("synced-derive")
+            .with_tags(&["mylib-derive-v0.1.0", "v0.1.1", "mylib-v0.1.1"])
+            .with_commit_notes(
+                "git log --format=%x1e%h%n%B mylib-derive-v0.1.0..HEAD -- mylib-derive",
+                "\x1ea1a1a1a\nfeat: support enums\n\x1eb2b2b2b\nchore: fix typo in README\n",
+            )
+            .with_commit_notes(
+                "git log --format=%x1e%h%n%B mylib-v0.1.1..HEAD -- mylib",
+                "\x1ec3c3c3c\nfix(derive)!: require `Debug`\n\x1ed4d4d4d\nperf: cache lookups\n",
+            ),
+        expect![[r#"
+            ::group::INIT
+            mylib@0.1.2 depends on mylib-derive@0.1.2
             publishable packages in workspace: [mylib-derive@0.1.2, mylib@0.1.2]
             INIT: 0.00ns
             ::endgroup::
@@ -677,28 +1328,503 @@ fn synced_derive() {
             TEST: 0.00ns
             ::endgroup::
             ::group::PUBLISH
-            existing git tags: ["mylib-v0.1.2"]
-            1 package needs publishing: [mylib-derive@0.1.2]
-            publishing mylib-derive@0.1.2
-            > cargo publish --no-verify -p mylib-derive --token dummy-token
-            > git tag mylib-derive-v0.1.2
+            existing git tags: ["mylib-derive-v0.1.0", "v0.1.1", "mylib-v0.1.1"]
+            generating release notes for mylib-derive@0.1.2 from commits in mylib-derive-v0.1.0..HEAD
+            generating release notes for mylib@0.1.2 from commits in mylib-v0.1.1..HEAD
+            2 packages need publishing: [mylib-derive@0.1.2, mylib@0.1.2]
+            publishing mylib-derive@0.1.2
+            > cargo publish --no-verify -p mylib-derive --token dummy-token
+            publishing mylib@0.1.2
+            > cargo publish --no-verify -p mylib --token dummy-token
+            > git tag v0.1.2
+            > git push --tags
+            > gh release create v0.1.2 --notes-file - <<<EOF
+            # mylib-derive 0.1.2
+
+            ## Features
+
+            - support enums (a1a1a1a)
+
+            # mylib 0.1.2
+
+            ## Breaking Changes
+
+            - **derive:** require `Debug` (c3c3c3c)
+
+            ## Performance
+
+            - cache lookups (d4d4d4d)
+            EOF
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+/// Attachments can be added to releases whose notes are generated from commits.
+#[test]
+fn commit_release_notes_with_attachments() {
+    check_output(
+        Params::test("single-package")
+            .with_tags(&["v2.2.1"])
+            .with_commit_notes(
+                "git log --format=%x1e%h%n%B v2.2.1..HEAD -- .",
+                "\x1ea1a1a1a\nfeat: add a flag\n",
+            )
+            .with_attachments(
+                "single-package",
+                "sludge-cicd-test-projects/single-package/Cargo.toml",
+            ),
+        expect![[r#"
+            ::group::INIT
+            publishable packages in workspace: [single-package@2.2.2]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: ["v2.2.1"]
+            generating release notes for single-package@2.2.2 from commits in v2.2.1..HEAD
+            package 'single-package' has 1 release attachments: ["sludge-cicd-test-projects/single-package/Cargo.toml"]
+            1 package needs publishing: [single-package@2.2.2]
+            publishing single-package@2.2.2
+            > cargo publish --no-verify -p single-package --token dummy-token
+            > git tag v2.2.2
+            > git push --tags
+            > gh release create v2.2.2 --notes-file - <<<EOF
+            ### Features
+
+            - add a flag (a1a1a1a)
+            EOF
+            > gh release upload v2.2.2 sludge-cicd-test-projects/single-package/Cargo.toml
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+#[test]
+fn dep_graph() {
+    check_output(
+        Params::test("graph"),
+        expect![[r#"
+            ::group::INIT
+            b@0.1.0 depends on a@0.1.0
+            b@0.1.0 depends on d@0.1.0
+            c@0.1.0 depends on a@0.1.0
+            e@0.1.0 depends on c@0.1.0
+            e@0.1.0 depends on d@0.1.0
+            publishable packages in workspace: [a@0.1.0, d@0.1.0, b@0.1.0, c@0.1.0, e@0.1.0]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: []
+            5 packages need publishing: [a@0.1.0, d@0.1.0, b@0.1.0, c@0.1.0, e@0.1.0]
+            publishing a@0.1.0
+            > cargo publish --no-verify -p a --token dummy-token
+            publishing d@0.1.0
+            > cargo publish --no-verify -p d --token dummy-token
+            publishing b@0.1.0
+            > cargo publish --no-verify -p b --token dummy-token
+            publishing c@0.1.0
+            > cargo publish --no-verify -p c --token dummy-token
+            publishing e@0.1.0
+            > cargo publish --no-verify -p e --token dummy-token
+            > git tag v0.1.0
+            > git push --tags
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+#[test]
+fn nonvirtual_workspace_changelog() {
+    // Non-virtual workspace with 2 packages published at 1.0.0.
+    // There is one shared CHANGELOG.md, so there should be a single tag and release.
+    check_output(
+        Params::test("nonvirtual-workspace-changelog"),
+        expect![[r#"
+            ::group::INIT
+            publishable packages in workspace: [subpackage@1.0.0, toplevel@1.0.0]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: []
+            2 packages need publishing: [subpackage@1.0.0, toplevel@1.0.0]
+            publishing subpackage@1.0.0
+            > cargo publish --no-verify -p subpackage --token dummy-token
+            publishing toplevel@1.0.0
+            > cargo publish --no-verify -p toplevel --token dummy-token
+            > git tag v1.0.0
+            > git push --tags
+            > gh release create v1.0.0 --notes-file - <<<EOF
+            # subpackage 1.0.0
+
+            - Subpackage Bla
+
+            # toplevel 1.0.0
+
+            - Toplevel Bla
+
+            ## blabla 1.0.0
+
+            ```rust
+            {
+                code example :)
+            }
+            ```
+            EOF
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+
+    // If a tag indicates that subpackage 1.0.0 was already published, only `toplevel` should be
+    // published and tagged, and only `toplevel` should get a release (which should only contain its
+    // release notes).
+    check_output(
+        Params::test("nonvirtual-workspace-changelog").with_tags(&["subpackage-v1.0.0"]),
+        expect![[r#"
+            ::group::INIT
+            publishable packages in workspace: [subpackage@1.0.0, toplevel@1.0.0]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: ["subpackage-v1.0.0"]
+            1 package needs publishing: [toplevel@1.0.0]
+            publishing toplevel@1.0.0
+            > cargo publish --no-verify -p toplevel --token dummy-token
+            > git tag toplevel-v1.0.0
+            > git push --tags
+            > gh release create toplevel-v1.0.0 --notes-file - <<<EOF
+            - Toplevel Bla
+
+            ### blabla 1.0.0
+
+            ```rust
+            {
+                code example :)
+            }
+            ```
+            EOF
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+#[test]
+fn single_package_changelog() {
+    check_output(
+        Params::test("single-package-changelog"),
+        expect![[r#"
+            ::group::INIT
+            publishable packages in workspace: [mypackage@0.1.0]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: []
+            1 package needs publishing: [mypackage@0.1.0]
+            publishing mypackage@0.1.0
+            > cargo publish --no-verify -p mypackage --token dummy-token
+            > git tag v0.1.0
+            > git push --tags
+            > gh release create v0.1.0 --notes-file - <<<EOF
+            Notes for 0.1.0
+            EOF
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+#[test]
+fn changelog_similar_versions() {
+    check_output(
+        Params::test("changelog-similar-versions"),
+        expect![[r#"
+            ::group::INIT
+            publishable packages in workspace: [mypackage@0.1.3]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: []
+            1 package needs publishing: [mypackage@0.1.3]
+            publishing mypackage@0.1.3
+            > cargo publish --no-verify -p mypackage --token dummy-token
+            > git tag v0.1.3
+            > git push --tags
+            > gh release create v0.1.3 --notes-file - <<<EOF
+            Notes for 0.1.3
+            EOF
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+#[test]
+fn keep_a_changelog() {
+    check_output(
+        Params::test("keep-a-changelog"),
+        expect![[r#"
+            ::group::INIT
+            publishable packages in workspace: [mypackage@1.2.0]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: []
+            1 package needs publishing: [mypackage@1.2.0]
+            publishing mypackage@1.2.0
+            > cargo publish --no-verify -p mypackage --token dummy-token
+            > git tag v1.2.0
+            > git push --tags
+            > gh release create v1.2.0 --notes-file - <<<EOF
+            ### Added
+
+            - New feature, see [#3](https://github.com/user/mypackage/issues/3).
+            EOF
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+#[test]
+fn release_notes_links() {
+    check_output(
+        Params::test("release-notes-links"),
+        expect![[r#"
+            ::group::INIT
+            publishable packages in workspace: [tool@0.3.0]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: []
+            1 package needs publishing: [tool@0.3.0]
+            publishing tool@0.3.0
+            > cargo publish --no-verify -p tool --token dummy-token
+            > git tag tool-v0.3.0
+            > git push --tags
+            > gh release create tool-v0.3.0 --notes-file - <<<EOF
+            - Added a `--color` option ([#12](https://github.com/user/tool/pull/12)). See [the docs](https://github.com/user/tool/blob/1234567890abcdef/crates/tool/docs/usage.md#color) and the
+              [workspace README](https://github.com/user/tool/blob/1234567890abcdef/README.md).
+            - Fixed a crash on empty input ([GH-3](https://github.com/user/tool/issues/3)).
+            - ![Screenshot](https://github.com/user/tool/raw/1234567890abcdef/crates/tool/screenshot.png)
+            - Unchanged: [website](https://example.com/#1), `#4`, and code blocks:
+
+              ```
+              tool --color=#ff0000 [x](x.md)
+              ```
+            EOF
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+#[test]
+fn changelog_lint_errors() {
+    check_error(
+        Params::test("changelog-lint-errors"),
+        expect![[r#"
+            found 1 problem in changelogs
+        "#]],
+        expect![[r#"
+            ::group::INIT
+            ::error file=sludge-cicd-test-projects/changelog-lint-errors/CHANGELOG.md,line=7,col=1,endColumn=9::0.2.0 is listed below 0.1.0, but versions should be in descending order
+            error: 0.2.0 is listed below 0.1.0, but versions should be in descending order
+             --> sludge-cicd-test-projects/changelog-lint-errors/CHANGELOG.md:7:1
+              |
+            7 | ## v0.2.0
+              | ^^^^^^^^^
+            INIT: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+#[test]
+fn multiple_changelogs() {
+    check_output(
+        Params::test("workspace-with-package-changelog"),
+        expect![[r#"
+            ::group::INIT
+            publishable packages in workspace: [a@0.1.0, b@0.1.0]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: []
+            2 packages need publishing: [a@0.1.0, b@0.1.0]
+            publishing a@0.1.0
+            > cargo publish --no-verify -p a --token dummy-token
+            publishing b@0.1.0
+            > cargo publish --no-verify -p b --token dummy-token
+            > git tag a-v0.1.0
+            > git tag b-v0.1.0
+            > git push --tags
+            > gh release create a-v0.1.0 --notes-file - <<<EOF
+            - entry for `a`
+            EOF
+            > gh release create b-v0.1.0 --notes-file - <<<EOF
+            - entry for `b`
+            EOF
+            PUBLISH: 0.00ns
+            ::endgroup::
+        "#]],
+    );
+}
+
+#[test]
+fn changelog_shared() {
+    check_output(
+        Params::test("changelog-shared"),
+        expect![[r#"
+            ::group::INIT
+            publishable packages in workspace: [derive@0.1.0, shared@0.1.0]
+            INIT: 0.00ns
+            ::endgroup::
+            ::group::BUILD
+            > cargo test --workspace --no-run
+            BUILD: 0.00ns
+            ::endgroup::
+            ::group::BUILD_DOCS
+            > cargo doc --workspace
+            BUILD_DOCS: 0.00ns
+            ::endgroup::
+            ::group::TEST
+            > cargo test --workspace
+            TEST: 0.00ns
+            ::endgroup::
+            ::group::PUBLISH
+            existing git tags: []
+            2 packages need publishing: [derive@0.1.0, shared@0.1.0]
+            publishing derive@0.1.0
+            > cargo publish --no-verify -p derive --token dummy-token
+            publishing shared@0.1.0
+            > cargo publish --no-verify -p shared --token dummy-token
+            > git tag v0.1.0
             > git push --tags
+            > gh release create v0.1.0 --notes-file - <<<EOF
+            - shared changelog for `derive` and `shared`
+            EOF
             PUBLISH: 0.00ns
             ::endgroup::
         "#]],
     );
-}
 
-#[test]
-fn dep_graph() {
     check_output(
-        Params::test("graph"),
+        Params::test("changelog-shared").with_tags(&["shared-v0.1.0"]),
         expect![[r#"
             ::group::INIT
-            b@0.1.0 depends on a@0.1.0
-            b@0.1.0 depends on d@0.1.0
-            c@0.1.0 depends on a@0.1.0
-            publishable packages in workspace: [a@0.1.0, d@0.1.0, b@0.1.0, c@0.1.0]
+            publishable packages in workspace: [derive@0.1.0, shared@0.1.0]
             INIT: 0.00ns
             ::endgroup::
             ::group::BUILD
@@ -714,18 +1840,15 @@ fn dep_graph() {
             TEST: 0.00ns
             ::endgroup::
             ::group::PUBLISH
-            existing git tags: []
-            4 packages need publishing: [a@0.1.0, d@0.1.0, b@0.1.0, c@0.1.0]
-            publishing a@0.1.0
-            > cargo publish --no-verify -p a --token dummy-token
-            publishing d@0.1.0
-            > cargo publish --no-verify -p d --token dummy-token
-            publishing b@0.1.0
-            > cargo publish --no-verify -p b --token dummy-token
-            publishing c@0.1.0
-            > cargo publish --no-verify -p c --token dummy-token
-            > git tag v0.1.0
+            existing git tags: ["shared-v0.1.0"]
+            1 package needs publishing: [derive@0.1.0]
+            publishing derive@0.1.0
+            > cargo publish --no-verify -p derive --token dummy-token
+            > git tag derive-v0.1.0
             > git push --tags
+            > gh release create derive-v0.1.0 --notes-file - <<<EOF
+            - shared changelog for `derive` and `shared`
+            EOF
             PUBLISH: 0.00ns
             ::endgroup::
         "#]],
@@ -733,14 +1856,12 @@ fn dep_graph() {
 }
 
 #[test]
-fn nonvirtual_workspace_changelog() {
-    // Non-virtual workspace with 2 packages published at 1.0.0.
-    // There is one shared CHANGELOG.md, so there should be a single tag and release.
+fn merged_release_notes() {
     check_output(
-        Params::test("nonvirtual-workspace-changelog"),
+        Params::test("merged-release-notes"),
         expect![[r#"
             ::group::INIT
-            publishable packages in workspace: [subpackage@1.0.0, toplevel@1.0.0]
+            publishable packages in workspace: [a@0.2.0, b@0.2.0, c@0.2.0]
             INIT: 0.00ns
             ::endgroup::
             ::group::BUILD
@@ -757,43 +1878,47 @@ fn nonvirtual_workspace_changelog() {
             ::endgroup::
             ::group::PUBLISH
             existing git tags: []
-            2 packages need publishing: [subpackage@1.0.0, toplevel@1.0.0]
-            publishing subpackage@1.0.0
-            > cargo publish --no-verify -p subpackage --token dummy-token
-            publishing toplevel@1.0.0
-            > cargo publish --no-verify -p toplevel --token dummy-token
-            > git tag v1.0.0
+            3 packages need publishing: [a@0.2.0, b@0.2.0, c@0.2.0]
+            publishing a@0.2.0
+            > cargo publish --no-verify -p a --token dummy-token
+            publishing b@0.2.0
+            > cargo publish --no-verify -p b --token dummy-token
+            publishing c@0.2.0
+            > cargo publish --no-verify -p c --token dummy-token
+            > git tag v0.2.0
             > git push --tags
-            > gh release create v1.0.0 --notes-file - <<<EOF
-            # subpackage 1.0.0
+            > gh release create v0.2.0 --notes-file - <<<EOF
+            # a 0.2.0, c 0.2.0
 
-            - Subpackage Bla
+            - Updated dependencies.
 
-            # toplevel 1.0.0
+            # b 0.2.0
 
-            - Toplevel Bla
+            ## Added
 
-            ### blabla 1.0.0
+            - New `b::thing`.
 
-            ```rust
-            {
-                code example :)
-            }
-            ```
+            ### Details
+
+            `b::thing` does things.
+
+            ## Fixed
+
+            - Bug in `b::other`.
             EOF
             PUBLISH: 0.00ns
             ::endgroup::
         "#]],
     );
+}
 
-    // If a tag indicates that subpackage 1.0.0 was already published, only `toplevel` should be
-    // published and tagged, and only `toplevel` should get a release (which should only contain its
-    // release notes).
+#[test]
+fn changelog_subsections() {
     check_output(
-        Params::test("nonvirtual-workspace-changelog").with_tags(&["subpackage-v1.0.0"]),
+        Params::test("changelog-subsections"),
         expect![[r#"
             ::group::INIT
-            publishable packages in workspace: [subpackage@1.0.0, toplevel@1.0.0]
+            publishable packages in workspace: [foo@0.5.0, foo-derive@0.5.0, foo-utils@0.5.0]
             INIT: 0.00ns
             ::endgroup::
             ::group::BUILD
@@ -809,22 +1934,36 @@ fn nonvirtual_workspace_changelog() {
             TEST: 0.00ns
             ::endgroup::
             ::group::PUBLISH
-            existing git tags: ["subpackage-v1.0.0"]
-            1 package needs publishing: [toplevel@1.0.0]
-            publishing toplevel@1.0.0
-            > cargo publish --no-verify -p toplevel --token dummy-token
-            > git tag toplevel-v1.0.0
+            existing git tags: []
+            3 packages need publishing: [foo@0.5.0, foo-derive@0.5.0, foo-utils@0.5.0]
+            publishing foo@0.5.0
+            > cargo publish --no-verify -p foo --token dummy-token
+            publishing foo-derive@0.5.0
+            > cargo publish --no-verify -p foo-derive --token dummy-token
+            publishing foo-utils@0.5.0
+            > cargo publish --no-verify -p foo-utils --token dummy-token
+            > git tag v0.5.0
             > git push --tags
-            > gh release create toplevel-v1.0.0 --notes-file - <<<EOF
-            - Toplevel Bla
+            > gh release create v0.5.0 --notes-file - <<<EOF
+            # foo 0.5.0
 
-            ### blabla 1.0.0
+            This release requires Rust 1.80.
 
-            ```rust
-            {
-                code example :)
-            }
-            ```
+            - Added `foo::bar`.
+
+            ## Details
+
+            More about `foo::bar`.
+
+            # foo-derive 0.5.0
+
+            This release requires Rust 1.80.
+
+            - Fixed a bug in `#[derive(Foo)]`.
+
+            # foo-utils 0.5.0
+
+            This release requires Rust 1.80.
             EOF
             PUBLISH: 0.00ns
             ::endgroup::
@@ -833,12 +1972,12 @@ fn nonvirtual_workspace_changelog() {
 }
 
 #[test]
-fn single_package_changelog() {
+fn changelog_sanitize() {
     check_output(
-        Params::test("single-package-changelog"),
+        Params::test("changelog-sanitize"),
         expect![[r#"
             ::group::INIT
-            publishable packages in workspace: [mypackage@0.1.0]
+            publishable packages in workspace: [sanitize@1.1.0]
             INIT: 0.00ns
             ::endgroup::
             ::group::BUILD
@@ -855,13 +1994,20 @@ fn single_package_changelog() {
             ::endgroup::
             ::group::PUBLISH
             existing git tags: []
-            1 package needs publishing: [mypackage@0.1.0]
-            publishing mypackage@0.1.0
-            > cargo publish --no-verify -p mypackage --token dummy-token
-            > git tag v0.1.0
+            1 package needs publishing: [sanitize@1.1.0]
+            publishing sanitize@1.1.0
+            > cargo publish --no-verify -p sanitize --token dummy-token
+            > git tag v1.1.0
             > git push --tags
-            > gh release create v0.1.0 --notes-file - <<<EOF
-            Notes for 0.1.0
+            > gh release create v1.1.0 --notes-file - <<<EOF
+            - Added `--color`.
+            - Fixed a crash on empty input.
+
+            HTML comments in code are kept:
+
+            ```html
+            <!-- example -->
+            ```
             EOF
             PUBLISH: 0.00ns
             ::endgroup::
@@ -869,13 +2015,14 @@ fn single_package_changelog() {
     );
 }
 
+/// A single package's `changelog` setting doesn't make its changelog package-specific.
 #[test]
-fn multiple_changelogs() {
+fn changelog_single_package_config() {
     check_output(
-        Params::test("workspace-with-package-changelog"),
+        Params::test("changelog-single-package-config"),
         expect![[r#"
             ::group::INIT
-            publishable packages in workspace: [a@0.1.0, b@0.1.0]
+            publishable packages in workspace: [mypkg@1.0.0]
             INIT: 0.00ns
             ::endgroup::
             ::group::BUILD
@@ -892,19 +2039,13 @@ fn multiple_changelogs() {
             ::endgroup::
             ::group::PUBLISH
             existing git tags: []
-            2 packages need publishing: [a@0.1.0, b@0.1.0]
-            publishing a@0.1.0
-            > cargo publish --no-verify -p a --token dummy-token
-            publishing b@0.1.0
-            > cargo publish --no-verify -p b --token dummy-token
-            > git tag a-v0.1.0
-            > git tag b-v0.1.0
+            1 package needs publishing: [mypkg@1.0.0]
+            publishing mypkg@1.0.0
+            > cargo publish --no-verify -p mypkg --token dummy-token
+            > git tag v1.0.0
             > git push --tags
-            > gh release create a-v0.1.0 --notes-file - <<<EOF
-            - entry for `a`
-            EOF
-            > gh release create b-v0.1.0 --notes-file - <<<EOF
-            - entry for `b`
+            > gh release create v1.0.0 --notes-file - <<<EOF
+            - First stable release.
             EOF
             PUBLISH: 0.00ns
             ::endgroup::
@@ -913,12 +2054,12 @@ fn multiple_changelogs() {
 }
 
 #[test]
-fn changelog_shared() {
+fn changelog_config() {
     check_output(
-        Params::test("changelog-shared"),
+        Params::test("changelog-config"),
         expect![[r#"
             ::group::INIT
-            publishable packages in workspace: [derive@0.1.0, shared@0.1.0]
+            publishable packages in workspace: [a@0.1.0, b@0.2.0, c@1.0.0]
             INIT: 0.00ns
             ::endgroup::
             ::group::BUILD
@@ -935,26 +2076,39 @@ fn changelog_shared() {
             ::endgroup::
             ::group::PUBLISH
             existing git tags: []
-            2 packages need publishing: [derive@0.1.0, shared@0.1.0]
-            publishing derive@0.1.0
-            > cargo publish --no-verify -p derive --token dummy-token
-            publishing shared@0.1.0
-            > cargo publish --no-verify -p shared --token dummy-token
-            > git tag v0.1.0
+            3 packages need publishing: [a@0.1.0, b@0.2.0, c@1.0.0]
+            publishing a@0.1.0
+            > cargo publish --no-verify -p a --token dummy-token
+            publishing b@0.2.0
+            > cargo publish --no-verify -p b --token dummy-token
+            publishing c@1.0.0
+            > cargo publish --no-verify -p c --token dummy-token
+            > git tag a-v0.1.0
+            > git tag b-v0.2.0
+            > git tag c-v1.0.0
             > git push --tags
-            > gh release create v0.1.0 --notes-file - <<<EOF
-            - shared changelog for `derive` and `shared`
+            > gh release create a-v0.1.0 --notes-file - <<<EOF
+            - entry for `a` from `changelogs/a.md`
+            EOF
+            > gh release create b-v0.2.0 --notes-file - <<<EOF
+            - entry for `b` from `docs/changelog.md`
+            EOF
+            > gh release create c-v1.0.0 --notes-file - <<<EOF
+            - entry for `c` from `c/HISTORY.md`
             EOF
             PUBLISH: 0.00ns
             ::endgroup::
         "#]],
     );
+}
 
+#[test]
+fn changelog_fragments() {
     check_output(
-        Params::test("changelog-shared").with_tags(&["shared-v0.1.0"]),
+        Params::test("changelog-fragments"),
         expect![[r#"
             ::group::INIT
-            publishable packages in workspace: [derive@0.1.0, shared@0.1.0]
+            publishable packages in workspace: [a@0.2.0, b@0.2.0, c@1.0.0]
             INIT: 0.00ns
             ::endgroup::
             ::group::BUILD
@@ -970,14 +2124,48 @@ fn changelog_shared() {
             TEST: 0.00ns
             ::endgroup::
             ::group::PUBLISH
-            existing git tags: ["shared-v0.1.0"]
-            1 package needs publishing: [derive@0.1.0]
-            publishing derive@0.1.0
-            > cargo publish --no-verify -p derive --token dummy-token
-            > git tag derive-v0.1.0
+            existing git tags: []
+            3 packages need publishing: [a@0.2.0, b@0.2.0, c@1.0.0]
+            publishing a@0.2.0
+            > cargo publish --no-verify -p a --token dummy-token
+            publishing b@0.2.0
+            > cargo publish --no-verify -p b --token dummy-token
+            publishing c@1.0.0
+            > cargo publish --no-verify -p c --token dummy-token
+            > git tag a-v0.2.0
+            > git tag b-v0.2.0
+            > git tag c-v1.0.0
             > git push --tags
-            > gh release create derive-v0.1.0 --notes-file - <<<EOF
-            - shared changelog for `derive` and `shared`
+            > gh release create a-v0.2.0 --notes-file - <<<EOF
+            - Updated dependencies.
+
+            ### Added
+
+            - Added `a::Thing`.
+
+              It does things.
+
+            ### Fixed
+
+            - Fixed a crash when the input is empty.
+            EOF
+            > gh release create b-v0.2.0 --notes-file - <<<EOF
+            - Updated dependencies.
+
+            ### Added
+
+            - Added `a::Thing`.
+
+              It does things.
+
+            ### Fixed
+
+            - Fixed a crash when the input is empty.
+            EOF
+            > gh release create c-v1.0.0 --notes-file - <<<EOF
+            ### Changed
+
+            - Stabilized the API.
             EOF
             PUBLISH: 0.00ns
             ::endgroup::
@@ -999,3 +2187,198 @@ fn changelog_version_missing() {
         "#]],
     );
 }
+
+#[test]
+fn tokens_are_hidden_from_subprocesses() {
+    env::set_var("CARGO_REGISTRIES_HIDDEN_TEST_TOKEN", "secret");
+    let command = command("cargo test");
+    let removed = command
+        .get_envs()
+        .filter(|(_, value)| value.is_none())
+        .map(|(key, _)| key.to_str().unwrap())
+        .collect::<Vec<_>>();
+    for key in [
+        "CRATES_IO_TOKEN",
+        "GITHUB_TOKEN",
+        "CARGO_REGISTRIES_HIDDEN_TEST_TOKEN",
+    ] {
+        assert!(removed.contains(&key), "{key} is passed to subprocesses");
+    }
+}
+
+#[test]
+fn changelog_assemble() {
+    let dir = copy_test_dir("changelog-fragments", "assemble-only");
+    let workspace = Workspace::get(dir.clone()).unwrap();
+    let packages = workspace.packages().unwrap();
+    assemble_fragments(&workspace, &packages, Some("c")).unwrap();
+    expect![[r#"
+        CHANGELOG.md
+        Cargo.toml
+        a/Cargo.toml
+        b/Cargo.toml
+        c/Cargo.toml
+        changelog.d/12.fixed.md
+        changelog.d/15.added.md
+        changelog.d/README.md
+        changelog.d/deps.md
+
+        --- CHANGELOG.md ---
+        # Changelog
+
+        ## v1.0.0 - <DATE>
+
+        ### Changed
+
+        - Stabilized the API.
+
+        ## v0.1.0
+
+        - Initial release.
+    "#]]
+    .assert_eq(&describe_dir(&dir));
+    let error = assemble_fragments(&workspace, &packages, Some("c")).unwrap_err();
+    expect!["no changelog fragments found"].assert_eq(&error.to_string());
+    fs::remove_dir_all(&dir).unwrap();
+
+    let dir = copy_test_dir("changelog-fragments", "assemble-all");
+    let workspace = Workspace::get(dir.clone()).unwrap();
+    let packages = workspace.packages().unwrap();
+    assemble_fragments(&workspace, &packages, None).unwrap();
+    expect![[r#"
+        CHANGELOG.md
+        Cargo.toml
+        a/Cargo.toml
+        b/Cargo.toml
+        c/Cargo.toml
+        changelog.d/README.md
+
+        --- CHANGELOG.md ---
+        # Changelog
+
+        ## v1.0.0 - <DATE>
+
+        ### Changed
+
+        - Stabilized the API.
+
+        ## v0.2.0 - <DATE>
+
+        - Updated dependencies.
+
+        ### Added
+
+        - Added `a::Thing`.
+
+          It does things.
+
+        ### Fixed
+
+        - Fixed a crash when the input is empty.
+
+        ## v0.1.0
+
+        - Initial release.
+    "#]]
+    .assert_eq(&describe_dir(&dir));
+    fs::remove_dir_all(&dir).unwrap();
+
+    // `a` has its own changelog, but no fragment directory of its own.
+    let dir = copy_test_dir("changelog-fragments", "assemble-shared");
+    fs::write(dir.join("a/CHANGELOG.md"), "# Changelog\n").unwrap();
+    let workspace = Workspace::get(dir.clone()).unwrap();
+    let packages = workspace.packages().unwrap();
+    let error = assemble_fragments(&workspace, &packages, None).unwrap_err();
+    let error = error.to_string().replace(&*dir.to_string_lossy(), "<DIR>");
+    expect![[r#"fragment directory '<DIR>/changelog.d' is shared by `a` and `b`, which have different changelogs (packages with their own changelog need their own fragment directory)"#]].assert_eq(&error);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn changelog_release() {
+    let release = |dir: &Path, args: &[&str]| {
+        let args = ["release"].iter().chain(args).map(|arg| arg.to_string());
+        changelog_command(dir.to_path_buf(), &args.collect::<Vec<_>>())
+    };
+
+    // `b` has its own changelog.
+    let dir = copy_test_dir("changelog-release", "release-package");
+    release(&dir, &["b"]).unwrap();
+    expect![[r#"
+        CHANGELOG.md
+        Cargo.toml
+        a/Cargo.toml
+        b/CHANGELOG.md
+        b/Cargo.toml
+
+        --- CHANGELOG.md ---
+        # Changelog
+
+        ## Unreleased
+
+        - Made `a` faster.
+
+        ## v0.1.0
+
+        - Initial release.
+
+        --- b/CHANGELOG.md ---
+        # Changelog
+
+        ## [Unreleased]
+
+        ## [1.1.0] - <DATE>
+
+        - Fixed a crash in `b`.
+
+        ## [1.0.0] - 2026-01-01
+
+        - Stabilized the API.
+    "#]]
+    .assert_eq(&describe_dir(&dir));
+    fs::remove_dir_all(&dir).unwrap();
+
+    // `a` uses the workspace changelog.
+    let dir = copy_test_dir("changelog-release", "release-all");
+    release(&dir, &[]).unwrap();
+    expect![[r#"
+        CHANGELOG.md
+        Cargo.toml
+        a/Cargo.toml
+        b/CHANGELOG.md
+        b/Cargo.toml
+
+        --- CHANGELOG.md ---
+        # Changelog
+
+        ## Unreleased
+
+        ## v0.2.0 - <DATE>
+
+        - Made `a` faster.
+
+        ## v0.1.0
+
+        - Initial release.
+
+        --- b/CHANGELOG.md ---
+        # Changelog
+
+        ## [Unreleased]
+
+        ## [1.1.0] - <DATE>
+
+        - Fixed a crash in `b`.
+
+        ## [1.0.0] - 2026-01-01
+
+        - Stabilized the API.
+    "#]]
+    .assert_eq(&describe_dir(&dir));
+    fs::remove_dir_all(&dir).unwrap();
+
+    let dir = copy_test_dir("changelog-fragments", "release-none");
+    let error = release(&dir, &[]).unwrap_err();
+    expect!["no changelog has an `Unreleased` section"].assert_eq(&error.to_string());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,123 +1,1439 @@
-use std::fmt;
+//! A TOML 1.0 parser.
+//!
+//! [`Toml::parse`] turns a document into a tree of [`Table`]s and [`Value`]s, while remembering
+//! where every key and value came from in the source text.
 
-use crate::{utils::next_line, Result};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
 
-pub enum Value<'a> {
-    Str(&'a str),
+use crate::diagnostic::{Diagnostic, Span};
+
+/// All errors produced by this module point at the offending location in the document.
+type Result<T, E = Diagnostic> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Integer(i64),
+    Float(f64),
     Bool(bool),
+    Datetime(Datetime),
+    Array(Vec<Value>),
+    /// A table defined by a header or dotted keys, or an inline table.
+    Table(Table),
 }
 
-impl<'a> Value<'a> {
-    pub fn as_str(&self) -> Option<&'a str> {
+impl Value {
+    /// Returns a human-readable name of the value's type, for use in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Str(_) => "string",
+            Self::Integer(_) => "integer",
+            Self::Float(_) => "float",
+            Self::Bool(_) => "boolean",
+            Self::Datetime(_) => "datetime",
+            Self::Array(_) => "array",
+            Self::Table(_) => "table",
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
         if let Self::Str(v) = self {
             Some(v)
         } else {
             None
         }
     }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        if let Self::Integer(v) = self {
+            Some(*v)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value as a float. Integers are converted.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Self::Float(v) => Some(*v),
+            Self::Integer(v) => Some(*v as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        if let Self::Bool(v) = self {
+            Some(*v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_datetime(&self) -> Option<&Datetime> {
+        if let Self::Datetime(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        if let Self::Array(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&Table> {
+        if let Self::Table(t) = self {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value as a list of strings, or `None` if it isn't an array or contains anything
+    /// but strings.
+    pub fn as_str_array(&self) -> Option<Vec<&str>> {
+        self.as_array()?.iter().map(Value::as_str).collect()
+    }
+}
+
+/// A TOML offset date-time, local date-time, local date, or local time.
+///
+/// At least one of `date` and `time` is present, and `offset` is only present if both are.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Datetime {
+    pub date: Option<Date>,
+    pub time: Option<Time>,
+    pub offset: Option<Offset>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Time {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Offset {
+    /// UTC, written as `Z`.
+    Z,
+    /// Offset from UTC in minutes.
+    Minutes(i16),
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.hour, self.minute, self.second)?;
+        if self.nanosecond != 0 {
+            let frac = format!("{:09}", self.nanosecond);
+            write!(f, ".{}", frac.trim_end_matches('0'))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Offset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Offset::Z => f.write_str("Z"),
+            Offset::Minutes(m) => {
+                let sign = if m < 0 { '-' } else { '+' };
+                let m = m.unsigned_abs();
+                write!(f, "{sign}{:02}:{:02}", m / 60, m % 60)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Datetime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(date) = &self.date {
+            date.fmt(f)?;
+            if self.time.is_some() {
+                f.write_str("T")?;
+            }
+        }
+        if let Some(time) = &self.time {
+            time.fmt(f)?;
+        }
+        if let Some(offset) = &self.offset {
+            offset.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Datetime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// How a table came into existence.
+///
+/// TOML forbids defining a table more than once, and forbids extending inline tables, so we have
+/// to keep track of this while parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TableKind {
+    Root,
+    /// Created as the parent of a `[table.header]` or an array of tables, but not defined itself.
+    Implicit,
+    /// Defined with a `[table]` or `[[table]]` header.
+    Header,
+    /// Defined by a dotted key like `a.b = 0`.
+    Dotted,
+    /// An inline table like `{ a = 0 }`.
+    Inline,
+}
+
+#[derive(Clone)]
+pub struct Table {
+    kind: TableKind,
+    entries: Vec<Entry>,
+    /// For the root table and tables defined by a header, the end of the last line belonging to
+    /// the table. New key-value pairs can be inserted here.
+    end: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    key: String,
+    key_span: Span,
+    value: Value,
+    value_span: Span,
+    /// Whether `value` is an array created by `[[array.of.tables]]` headers.
+    array_of_tables: bool,
+}
+
+impl PartialEq for Table {
+    fn eq(&self, other: &Self) -> bool {
+        self.entries.len() == other.entries.len()
+            && self
+                .entries
+                .iter()
+                .zip(&other.entries)
+                .all(|(a, b)| a.key == b.key && a.value == b.value)
+    }
+}
+
+impl fmt::Debug for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.entries.iter().map(|e| (&e.key, &e.value)))
+            .finish()
+    }
+}
+
+impl Table {
+    fn new(kind: TableKind) -> Self {
+        Self {
+            kind,
+            entries: Vec::new(),
+            end: 0,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entry(key).map(|e| &e.value)
+    }
+
+    /// Returns whether this is an inline table like `{ a = 0 }`.
+    pub fn is_inline(&self) -> bool {
+        self.kind == TableKind::Inline
+    }
+
+    /// Iterates over all key-value pairs in the table, in the order they were defined in.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.entries.iter().map(|e| (&*e.key, &e.value))
+    }
+
+    /// Looks up the entry at `path`.
+    ///
+    /// On failure, returns the number of keys in `path` that could be resolved, and the entry the
+    /// last of them resolved to.
+    fn lookup(&self, path: &[&str]) -> Result<&Entry, (usize, Option<&Entry>)> {
+        let mut table = self;
+        let mut parent = None;
+        for (i, key) in path.iter().enumerate() {
+            let Some(entry) = table.entry(key) else {
+                return Err((i, parent));
+            };
+            if i == path.len() - 1 {
+                return Ok(entry);
+            }
+            parent = Some(entry);
+            match &entry.value {
+                Value::Table(t) => table = t,
+                _ => return Err((i + 1, parent)),
+            }
+        }
+        unreachable!("empty path")
+    }
+
+    fn entry(&self, key: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.key == key)
+    }
+
+    fn entry_mut(&mut self, key: &str) -> Option<&mut Entry> {
+        self.entries.iter_mut().find(|e| e.key == key)
+    }
+
+    fn push(&mut self, key: String, key_span: Span, value: Value, value_span: Span) {
+        self.entries.push(Entry {
+            key,
+            key_span,
+            value,
+            value_span,
+            array_of_tables: false,
+        });
+    }
 }
 
-pub struct Toml<'a>(pub &'a str);
+/// A parsed TOML document.
+pub struct Toml<'a> {
+    src: &'a str,
+    /// The file the document was read from, for diagnostics.
+    file: Option<PathBuf>,
+    root: Table,
+    headers: Vec<Header>,
+}
+
+/// Location of a `[table]` or `[[array]]` header.
+struct Header {
+    /// The key inside the brackets, as written.
+    name: Span,
+    /// The whole line containing the header, including the line terminator.
+    line: Span,
+}
 
 impl<'a> fmt::Debug for Toml<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
+        self.src.fmt(f)
     }
 }
 
 impl<'a> Toml<'a> {
-    pub fn get_field(&self, name: &str) -> Result<Value<'a>> {
-        for line in self.0.lines() {
-            if let Some((n, v)) = line.split_once('=') {
-                if n.trim() == name {
-                    let v = v.trim();
-                    if v.starts_with('"') {
-                        let mut esc = false;
-                        let mut end = None;
-                        for (i, c) in v[1..].char_indices() {
-                            if c == '\\' {
-                                esc = true;
-                            } else {
-                                if c == '"' && !esc {
-                                    end = Some(i);
-                                    break;
-                                }
-                                esc = false;
-                            }
+    pub fn parse(src: &'a str) -> Result<Self> {
+        Parser::new(src, None).parse()
+    }
+
+    /// Parses the contents of `file`, which will be referenced by all diagnostics.
+    pub fn parse_file(src: &'a str, file: &Path) -> Result<Self> {
+        Parser::new(src, Some(file)).parse()
+    }
+
+    pub fn root(&self) -> &Table {
+        &self.root
+    }
+
+    /// Looks up a value by the path of keys leading to it, starting at the document root.
+    ///
+    /// Since lookup operates on the parsed document, it does not matter whether the value was
+    /// defined via `[table]` headers, dotted keys, or inline tables.
+    pub fn get_path(&self, path: &[&str]) -> Result<&Value> {
+        match self.root.lookup(path) {
+            Ok(entry) => Ok(&entry.value),
+            Err((resolved, Some(parent))) if !matches!(parent.value, Value::Table(_)) => Err(self
+                .error_at(
+                    parent.value_span,
+                    format!(
+                        "`{}` is a {}, not a table",
+                        display_path(&path[..resolved]),
+                        parent.value.type_name()
+                    ),
+                )),
+            Err(_) => Err(self.error(path, format!("can't find `{}`", display_path(path)))),
+        }
+    }
+
+    /// Looks up a string by the path of keys leading to it.
+    pub fn get_str(&self, path: &[&str]) -> Result<&str> {
+        let value = self.get_path(path)?;
+        value.as_str().ok_or_else(|| {
+            let entry = self.root.lookup(path).unwrap();
+            self.error_at(
+                entry.value_span,
+                format!(
+                    "`{}` must be a string, but is a {}",
+                    display_path(path),
+                    value.type_name()
+                ),
+            )
+        })
+    }
+
+    /// Creates a diagnostic pointing at the key at `path`.
+    ///
+    /// If the key doesn't exist, the diagnostic points at the closest table that does, or at the
+    /// start of the document.
+    pub fn error(&self, path: &[&str], message: impl Into<String>) -> Diagnostic {
+        let span = match self.root.lookup(path) {
+            Ok(entry) | Err((_, Some(entry))) => entry.key_span,
+            Err((_, None)) => Span::new(0, 0),
+        };
+        self.error_at(span, message)
+    }
+
+    fn error_at(&self, span: Span, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(self.src, span, message).with_file(self.file.as_deref())
+    }
+}
+
+/// Format-preserving editing.
+// Nothing edits manifests yet.
+#[allow(dead_code)]
+impl<'a> Toml<'a> {
+    /// Returns a copy of the document with the value at `path` set to `value`.
+    ///
+    /// Everything except the edited value is preserved byte-for-byte, including comments,
+    /// whitespace, and the order of keys. If the key does not exist yet, a new key-value pair is
+    /// added to the table that should contain it, using dotted keys if necessary.
+    pub fn set_path(&self, path: &[&str], value: &Value) -> Result<String> {
+        assert!(!path.is_empty(), "cannot replace the document root");
+
+        // The innermost table along `path` that has its own place in the document (the root, a
+        // table with a header, or an inline table), the index in `path` where its keys start, and
+        // the location of its inline table.
+        let mut container = (&self.root, 0, None);
+        let mut table = &self.root;
+        for (i, key) in path.iter().enumerate() {
+            let Some(entry) = table.entry(key) else {
+                // If the table was only mentioned in headers of its subtables, or if we would
+                // create new top-level tables, add a new section at the end of the document
+                // instead of using dotted keys.
+                let new_section = table.kind == TableKind::Implicit
+                    || (table.kind == TableKind::Root && i != path.len() - 1);
+                let edit = if new_section {
+                    let (parent, last) = path.split_at(path.len() - 1);
+                    let mut text = String::new();
+                    if !self.src.trim().is_empty() {
+                        text.push('\n');
+                    }
+                    text += &format!("[{}]\n", display_path(parent));
+                    text += &format!("{} = {value}\n", display_path(last));
+                    self.insert_lines(self.src.len(), &text)
+                } else {
+                    let (container, start, inline) = container;
+                    let pair = format!("{} = {value}", display_path(&path[start..]));
+                    match inline {
+                        Some(span) => self.insert_inline(span, &pair),
+                        None => self.insert_lines(container.end, &format!("{pair}\n")),
+                    }
+                };
+                return self.verify_edit(edit, path, value);
+            };
+
+            match &entry.value {
+                Value::Table(t) if !t.is_inline() && i == path.len() - 1 => {
+                    return Err(self.error_at(
+                        entry.key_span,
+                        format!("cannot replace table `{}`", display_path(&path[..=i])),
+                    ));
+                }
+                Value::Array(_) if entry.array_of_tables => {
+                    return Err(self.error_at(
+                        entry.key_span,
+                        format!(
+                            "cannot edit array of tables `{}`",
+                            display_path(&path[..=i])
+                        ),
+                    ));
+                }
+                _ if i == path.len() - 1 => {
+                    let span = entry.value_span;
+                    let edit = format!(
+                        "{}{value}{}",
+                        &self.src[..span.start],
+                        &self.src[span.end..]
+                    );
+                    return self.verify_edit(edit, path, value);
+                }
+                Value::Table(t) => {
+                    match t.kind {
+                        TableKind::Header => container = (t, i + 1, None),
+                        TableKind::Inline => container = (t, i + 1, Some(entry.value_span)),
+                        _ => {}
+                    }
+                    table = t;
+                }
+                other => {
+                    return Err(self.error_at(
+                        entry.value_span,
+                        format!(
+                            "`{}` is a {}, not a table",
+                            display_path(&path[..=i]),
+                            other.type_name()
+                        ),
+                    ))
+                }
+            }
+        }
+        unreachable!()
+    }
+
+    /// Inserts `text` (one or more lines) at the start of a line at or after `pos`.
+    fn insert_lines(&self, pos: usize, text: &str) -> String {
+        let (before, after) = self.src.split_at(pos);
+        let crlf = self.src.contains("\r\n");
+        let line_start = before
+            .trim_end_matches(['\r', '\n'])
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let indent = &before[line_start..]
+            [..before[line_start..].len() - before[line_start..].trim_start().len()];
+
+        let mut out = before.to_string();
+        if !before.is_empty() && !before.ends_with('\n') {
+            out += if crlf { "\r\n" } else { "\n" };
+        }
+        for line in text.lines() {
+            if !line.is_empty() {
+                out += indent;
+            }
+            out += line;
+            out += if crlf { "\r\n" } else { "\n" };
+        }
+        out += after;
+        out
+    }
+
+    /// Inserts a `key = value` pair into the inline table at `span`.
+    fn insert_inline(&self, span: Span, pair: &str) -> String {
+        let inner = &self.src[span.start + 1..span.end - 1];
+        if inner.trim().is_empty() {
+            format!(
+                "{}{{ {pair} }}{}",
+                &self.src[..span.start],
+                &self.src[span.end..]
+            )
+        } else {
+            let pos = span.start + 1 + inner.trim_end().len();
+            format!("{}, {pair}{}", &self.src[..pos], &self.src[pos..])
+        }
+    }
+
+    fn verify_edit(&self, edit: String, path: &[&str], value: &Value) -> Result<String> {
+        match Toml::parse(&edit) {
+            Ok(toml) if toml.get_path(path).ok() == Some(value) => Ok(edit),
+            _ => Err(self.error(
+                path,
+                format!(
+                    "setting `{}` to `{value}` would produce an invalid document",
+                    display_path(path)
+                ),
+            )),
+        }
+    }
+
+    /// Returns the raw text of every table header in the document, along with the raw text of the
+    /// key-value pairs following it.
+    pub fn sections(&self) -> Vec<(&'a str, &'a str)> {
+        self.headers
+            .iter()
+            .enumerate()
+            .map(|(i, header)| {
+                let end = match self.headers.get(i + 1) {
+                    Some(next) => next.line.start,
+                    None => self.src.len(),
+                };
+                (
+                    &self.src[header.name.start..header.name.end],
+                    self.src[header.line.end..end].trim(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Formats a key path the way it would be written in a TOML document.
+fn display_path(path: &[&str]) -> String {
+    let mut out = String::new();
+    for (i, key) in path.iter().enumerate() {
+        if i != 0 {
+            out.push('.');
+        }
+        write_key(&mut out, key);
+    }
+    out
+}
+
+fn write_key(out: &mut String, key: &str) {
+    let bare = !key.is_empty()
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-');
+    if bare {
+        out.push_str(key);
+    } else {
+        write_string(out, key);
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_ascii_control() => {
+                use fmt::Write as _;
+                write!(out, "\\u{:04X}", u32::from(c)).ok();
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Formats values as TOML. Tables are written as inline tables.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => {
+                let mut out = String::new();
+                write_string(&mut out, s);
+                f.write_str(&out)
+            }
+            Value::Integer(i) => i.fmt(f),
+            Value::Float(v) if v.is_nan() => f.write_str("nan"),
+            Value::Float(v) if v.is_infinite() => {
+                f.write_str(if *v < 0.0 { "-inf" } else { "inf" })
+            }
+            Value::Float(v) if v.fract() == 0.0 => write!(f, "{v:.1}"),
+            Value::Float(v) => v.fmt(f),
+            Value::Bool(b) => b.fmt(f),
+            Value::Datetime(dt) => dt.fmt(f),
+            Value::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    item.fmt(f)?;
+                }
+                f.write_str("]")
+            }
+            Value::Table(t) if t.entries.is_empty() => f.write_str("{}"),
+            Value::Table(t) => {
+                f.write_str("{ ")?;
+                for (i, (key, value)) in t.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    let mut k = String::new();
+                    write_key(&mut k, key);
+                    write!(f, "{k} = {value}")?;
+                }
+                f.write_str(" }")
+            }
+        }
+    }
+}
+
+/// A dotted key, with the location of each of its parts.
+type Key = Vec<(String, Span)>;
+
+/// An error message and the location it refers to.
+type ParseError = (Span, String);
+
+struct Parser<'a> {
+    src: &'a str,
+    file: Option<PathBuf>,
+    pos: usize,
+    root: Table,
+    headers: Vec<Header>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str, file: Option<&Path>) -> Self {
+        Self {
+            src,
+            file: file.map(Path::to_path_buf),
+            pos: 0,
+            root: Table::new(TableKind::Root),
+            headers: Vec::new(),
+        }
+    }
+
+    fn parse(mut self) -> Result<Toml<'a>> {
+        // Path to the table that key-value pairs are currently added to.
+        let mut current = Vec::new();
+        loop {
+            self.skip_trivia()?;
+            let line_start = self.pos;
+            match self.peek() {
+                None => break,
+                Some(b'[') => {
+                    current = self.header()?;
+                    self.line_end()?;
+                    let header = self.headers.last_mut().unwrap();
+                    header.line = Span::new(line_start, self.pos);
+                    lookup_mut(&mut self.root, &current).end = self.pos;
+                }
+                Some(_) => {
+                    let key = self.key()?;
+                    self.ws();
+                    self.expect(b'=')?;
+                    self.ws();
+                    let start = self.pos;
+                    let value = self.value()?;
+                    let value_span = Span::new(start, self.pos);
+                    let table = lookup_mut(&mut self.root, &current);
+                    insert(table, key, value, value_span).map_err(|e| self.error(e))?;
+                    self.line_end()?;
+                    lookup_mut(&mut self.root, &current).end = self.pos;
+                }
+            }
+        }
+
+        Ok(Toml {
+            src: self.src,
+            file: self.file,
+            root: self.root,
+            headers: self.headers,
+        })
+    }
+
+    /// Parses a `[table]` or `[[array]]` header and returns the path to the table it defines.
+    fn header(&mut self) -> Result<Vec<String>> {
+        self.expect(b'[')?;
+        let array = self.eat(b'[');
+        self.ws();
+        let start = self.pos;
+        let key = self.key()?;
+        let name = Span::new(start, self.pos);
+        self.ws();
+        self.expect(b']')?;
+        if array {
+            self.expect(b']')?;
+        }
+
+        self.headers.push(Header {
+            name,
+            line: Span::new(start, start),
+        });
+        define_table(&mut self.root, &key, array).map_err(|e| self.error(e))?;
+        Ok(key.into_iter().map(|(k, _)| k).collect())
+    }
+
+    fn key(&mut self) -> Result<Key> {
+        let mut key = Vec::new();
+        loop {
+            let start = self.pos;
+            let part = self.simple_key()?;
+            key.push((part, Span::new(start, self.pos)));
+
+            let end = self.pos;
+            self.ws();
+            if self.eat(b'.') {
+                self.ws();
+            } else {
+                // Leave trailing whitespace alone, so that the key's span doesn't include it.
+                self.pos = end;
+                return Ok(key);
+            }
+        }
+    }
+
+    fn simple_key(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(b'"') => {
+                self.pos += 1;
+                self.basic_string()
+            }
+            Some(b'\'') => {
+                self.pos += 1;
+                self.literal_string()
+            }
+            _ => {
+                let start = self.pos;
+                while let Some(b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-') = self.peek()
+                {
+                    self.pos += 1;
+                }
+                if start == self.pos {
+                    return Err(self.error_here(format!("expected a key, found {}", self.found())));
+                }
+                Ok(self.src[start..self.pos].to_string())
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        let rest = &self.src[self.pos..];
+        if rest.starts_with("\"\"\"") {
+            self.pos += 3;
+            self.multiline_basic_string().map(Value::Str)
+        } else if rest.starts_with('"') {
+            self.pos += 1;
+            self.basic_string().map(Value::Str)
+        } else if rest.starts_with("'''") {
+            self.pos += 3;
+            self.multiline_literal_string().map(Value::Str)
+        } else if rest.starts_with('\'') {
+            self.pos += 1;
+            self.literal_string().map(Value::Str)
+        } else if rest.starts_with('[') {
+            self.array()
+        } else if rest.starts_with('{') {
+            self.inline_table()
+        } else if rest.starts_with("true") {
+            self.pos += 4;
+            Ok(Value::Bool(true))
+        } else if rest.starts_with("false") {
+            self.pos += 5;
+            Ok(Value::Bool(false))
+        } else {
+            self.number_or_datetime()
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_trivia()?;
+            if self.eat(b']') {
+                break;
+            }
+            items.push(self.value()?);
+            self.skip_trivia()?;
+            if !self.eat(b',') {
+                self.expect(b']')?;
+                break;
+            }
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn inline_table(&mut self) -> Result<Value> {
+        self.expect(b'{')?;
+        let mut table = Table::new(TableKind::Inline);
+        self.ws();
+        if self.eat(b'}') {
+            return Ok(Value::Table(table));
+        }
+        loop {
+            let key = self.key()?;
+            self.ws();
+            self.expect(b'=')?;
+            self.ws();
+            let start = self.pos;
+            let value = self.value()?;
+            let value_span = Span::new(start, self.pos);
+            insert(&mut table, key, value, value_span).map_err(|e| self.error(e))?;
+            self.ws();
+            if self.eat(b',') {
+                self.ws();
+            } else {
+                self.expect(b'}')?;
+                return Ok(Value::Table(table));
+            }
+        }
+    }
+
+    /// Parses the rest of a `"basic string"` after the opening quote.
+    fn basic_string(&mut self) -> Result<String> {
+        let mut out = String::new();
+        loop {
+            match self.next_char() {
+                None | Some('\n') => return Err(self.error_here("unterminated string")),
+                Some('"') => return Ok(out),
+                Some('\\') => out.push(self.escape()?),
+                Some(c) => out.push(self.string_char(c)?),
+            }
+        }
+    }
+
+    /// Parses the rest of a `"""multi-line basic string"""` after the opening quotes.
+    fn multiline_basic_string(&mut self) -> Result<String> {
+        self.newline();
+        let mut out = String::new();
+        loop {
+            if self.multiline_end(b'"', &mut out)? {
+                return Ok(out);
+            }
+            if self.newline() {
+                out.push('\n');
+                continue;
+            }
+            match self.next_char() {
+                None => return Err(self.error_here("unterminated string")),
+                Some('\\') => {
+                    // A "line ending backslash" trims all whitespace up to the next
+                    // non-whitespace character.
+                    let start = self.pos;
+                    self.ws();
+                    if self.newline() {
+                        while self.newline() || matches!(self.peek(), Some(b' ' | b'\t')) {
+                            self.ws();
                         }
-                        let Some(end) = end else {
-                            panic!("unclosed string in '{line}'");
-                        };
-                        return Ok(Value::Str(&v[1..end + 1]));
-                    } else if v.split(|v: char| !v.is_alphanumeric()).next().unwrap() == "true" {
-                        return Ok(Value::Bool(true));
-                    } else if v.split(|v: char| !v.is_alphanumeric()).next().unwrap() == "false" {
-                        return Ok(Value::Bool(false));
+                    } else {
+                        self.pos = start;
+                        out.push(self.escape()?);
                     }
                 }
+                Some(c) => out.push(self.string_char(c)?),
             }
         }
-        Err(format!(
-            "can't find `{}` in\n----\n{}\n----\n",
-            name, self.0
-        ))?
     }
 
-    pub fn sections(&self) -> Vec<(&str, Toml<'a>)> {
-        // `Lines` has no stable `remainder` or `as_str` method, so we have to do this manually...
-        let mut remainder = self.0;
+    /// Parses the rest of a `'literal string'` after the opening quote.
+    fn literal_string(&mut self) -> Result<String> {
+        let start = self.pos;
+        loop {
+            match self.next_char() {
+                None | Some('\n') => return Err(self.error_here("unterminated string")),
+                Some('\'') => return Ok(self.src[start..self.pos - 1].to_string()),
+                Some(c) => {
+                    self.string_char(c)?;
+                }
+            }
+        }
+    }
 
-        // Find the first line that starts a section.
-        let mut section_name = loop {
-            let Some(line) = next_line(&mut remainder) else {
-                return Vec::new();
-            };
-            if let Some(name) = start_of_section_or_array(line) {
-                break name;
+    /// Parses the rest of a `'''multi-line literal string'''` after the opening quotes.
+    fn multiline_literal_string(&mut self) -> Result<String> {
+        self.newline();
+        let mut out = String::new();
+        loop {
+            if self.multiline_end(b'\'', &mut out)? {
+                return Ok(out);
             }
+            if self.newline() {
+                out.push('\n');
+                continue;
+            }
+            match self.next_char() {
+                None => return Err(self.error_here("unterminated string")),
+                Some(c) => out.push(self.string_char(c)?),
+            }
+        }
+    }
+
+    /// Checks for the closing delimiter of a multi-line string.
+    ///
+    /// Up to two quotes may directly precede the closing delimiter, and are part of the string.
+    fn multiline_end(&mut self, quote: u8, out: &mut String) -> Result<bool> {
+        let count = self.src.as_bytes()[self.pos..]
+            .iter()
+            .take_while(|&&b| b == quote)
+            .count();
+        match count {
+            0..=2 => Ok(false),
+            3..=5 => {
+                out.extend(std::iter::repeat_n(char::from(quote), count - 3));
+                self.pos += count;
+                Ok(true)
+            }
+            _ => Err(self.error_here("too many quotes at the end of a multi-line string")),
+        }
+    }
+
+    /// Parses an escape sequence after the backslash.
+    fn escape(&mut self) -> Result<char> {
+        let start = self.pos - 1;
+        let digits = match self.next_char() {
+            Some('b') => return Ok('\u{8}'),
+            Some('t') => return Ok('\t'),
+            Some('n') => return Ok('\n'),
+            Some('f') => return Ok('\u{c}'),
+            Some('r') => return Ok('\r'),
+            Some('"') => return Ok('"'),
+            Some('\\') => return Ok('\\'),
+            Some('u') => 4,
+            Some('U') => 8,
+            _ => return Err(self.error_at(start, "invalid escape sequence")),
         };
+        let hex = self.src[self.pos..].get(..digits).unwrap_or("");
+        let c = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error_at(start, "invalid unicode escape"))?;
+        self.pos += digits;
+        Ok(c)
+    }
 
-        let mut section_contents = &self.0[self.0.len() - remainder.len()..];
-        let mut out = Vec::new();
-        // Find the beginning of the next section, or the end of the file.
-        loop {
-            let contents = section_contents[..section_contents.len() - remainder.len()].trim();
-            let Some(line) = next_line(&mut remainder) else {
-                out.push((section_name, Toml(contents)));
+    /// Validates a character inside a string.
+    fn string_char(&self, c: char) -> Result<char> {
+        if (c.is_ascii_control() && c != '\t') || c == '\u{7f}' {
+            let pos = self.pos - c.len_utf8();
+            Err(self.error_at(pos, "control characters must be escaped"))
+        } else {
+            Ok(c)
+        }
+    }
+
+    fn number_or_datetime(&mut self) -> Result<Value> {
+        let start = self.pos;
+        self.eat_token();
+        let bytes = self.src.as_bytes();
+        let is_date = self.pos - start == 10 && bytes[start + 4] == b'-';
+        if is_date
+            && bytes.get(self.pos) == Some(&b' ')
+            && bytes
+                .get(self.pos + 1..self.pos + 4)
+                .is_some_and(|b| b[0].is_ascii_digit() && b[1].is_ascii_digit() && b[2] == b':')
+        {
+            // Date and time separated by a space.
+            self.pos += 1;
+            self.eat_token();
+        }
+
+        let text = &self.src[start..self.pos];
+        if text.is_empty() {
+            return Err(self.error_here(format!("expected a value, found {}", self.found())));
+        }
+        let looks_like_datetime = text.as_bytes().get(4) == Some(&b'-')
+            && text.as_bytes()[..4].iter().all(u8::is_ascii_digit)
+            || text.as_bytes().get(2) == Some(&b':');
+        let value = if looks_like_datetime {
+            parse_datetime(text).map(Value::Datetime)
+        } else {
+            parse_number(text)
+        };
+        value.ok_or_else(|| self.error_at(start, format!("invalid value `{text}`")))
+    }
+
+    fn eat_token(&mut self) {
+        while let Some(b'0'..=b'9' | b'A'..=b'Z' | b'a'..=b'z' | b'_' | b'+' | b'-' | b'.' | b':') =
+            self.peek()
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.as_bytes().get(self.pos).copied()
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.src[self.pos..].chars().next()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, b: u8) -> Result<()> {
+        if self.eat(b) {
+            Ok(())
+        } else {
+            Err(self.error_here(format!(
+                "expected `{}`, found {}",
+                char::from(b),
+                self.found()
+            )))
+        }
+    }
+
+    /// Skips spaces and tabs.
+    fn ws(&mut self) {
+        while let Some(b' ' | b'\t') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    /// Skips a line terminator, if there is one.
+    fn newline(&mut self) -> bool {
+        if self.src[self.pos..].starts_with("\r\n") {
+            self.pos += 2;
+            true
+        } else {
+            self.eat(b'\n')
+        }
+    }
+
+    /// Skips a comment, if there is one.
+    fn comment(&mut self) -> Result<()> {
+        if !self.eat(b'#') {
+            return Ok(());
+        }
+        while !matches!(self.peek(), None | Some(b'\n')) {
+            if self.src[self.pos..].starts_with("\r\n") {
                 break;
-            };
-            if let Some(name) = start_of_section_or_array(line) {
-                out.push((section_name, Toml(contents)));
-                section_name = name;
-                section_contents = &self.0[self.0.len() - remainder.len()..];
             }
+            let c = self.next_char().unwrap();
+            self.string_char(c)?;
         }
+        Ok(())
+    }
 
-        out
+    /// Skips whitespace, comments and line terminators.
+    fn skip_trivia(&mut self) -> Result<()> {
+        loop {
+            self.ws();
+            self.comment()?;
+            if !self.newline() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Expects the end of a line, optionally preceded by a comment.
+    fn line_end(&mut self) -> Result<()> {
+        self.ws();
+        self.comment()?;
+        if self.peek().is_none() || self.newline() {
+            Ok(())
+        } else {
+            Err(self.error_here(format!("expected newline, found {}", self.found())))
+        }
+    }
+
+    fn found(&self) -> String {
+        match self.src[self.pos..].chars().next() {
+            None => "end of file".into(),
+            Some('\n' | '\r') => "newline".into(),
+            Some(c) => format!("`{c}`"),
+        }
+    }
+
+    fn error(&self, (span, msg): ParseError) -> Diagnostic {
+        Diagnostic::new(self.src, span, msg).with_file(self.file.as_deref())
+    }
+
+    fn error_here(&self, msg: impl Into<String>) -> Diagnostic {
+        self.error_at(self.pos, msg)
     }
 
-    pub fn section(&self, name: &str) -> Option<Toml<'a>> {
-        self.sections()
-            .into_iter()
-            .find_map(|(n, toml)| if name == n { Some(toml) } else { None })
+    fn error_at(&self, pos: usize, msg: impl Into<String>) -> Diagnostic {
+        let len =
+            (self.src.get(pos..).and_then(|rest| rest.chars().next())).map_or(1, char::len_utf8);
+        Diagnostic::new(self.src, Span::new(pos, pos + len), msg).with_file(self.file.as_deref())
+    }
+}
+
+/// Finds the table a key-value pair belongs in after the header at `path` was parsed.
+fn lookup_mut<'t>(mut table: &'t mut Table, path: &[String]) -> &'t mut Table {
+    for key in path {
+        let entry = table.entry_mut(key).unwrap();
+        table = match &mut entry.value {
+            Value::Table(t) => t,
+            Value::Array(items) => match items.last_mut() {
+                Some(Value::Table(t)) => t,
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+    }
+    table
+}
+
+/// Creates the table for a `[table]` or `[[array]]` header.
+fn define_table(mut table: &mut Table, key: &Key, array: bool) -> Result<(), ParseError> {
+    let ((last, last_span), parents) = key.split_last().unwrap();
+    for (name, span) in parents {
+        if table.entry(name).is_none() {
+            table.push(
+                name.clone(),
+                *span,
+                Value::Table(Table::new(TableKind::Implicit)),
+                *span,
+            );
+        }
+        let entry = table.entry_mut(name).unwrap();
+        let is_array_of_tables = entry.array_of_tables;
+        table = match &mut entry.value {
+            Value::Table(t) if !t.is_inline() => t,
+            Value::Array(items) if is_array_of_tables => match items.last_mut() {
+                Some(Value::Table(t)) => t,
+                _ => unreachable!(),
+            },
+            Value::Table(_) => return Err((*span, format!("cannot extend inline table `{name}`"))),
+            _ => return Err((*span, format!("key `{name}` is not a table"))),
+        };
+    }
+
+    let new = || Value::Table(Table::new(TableKind::Header));
+    match table.entry_mut(last) {
+        None if array => table.entries.push(Entry {
+            key: last.clone(),
+            key_span: *last_span,
+            value: Value::Array(vec![new()]),
+            value_span: *last_span,
+            array_of_tables: true,
+        }),
+        None => table.push(last.clone(), *last_span, new(), *last_span),
+        Some(Entry {
+            value: Value::Array(items),
+            array_of_tables: true,
+            ..
+        }) if array => items.push(new()),
+        Some(
+            entry @ Entry {
+                value:
+                    Value::Table(Table {
+                        kind: TableKind::Implicit,
+                        ..
+                    }),
+                ..
+            },
+        ) if !array => {
+            entry.key_span = *last_span;
+            entry.value_span = *last_span;
+            if let Value::Table(t) = &mut entry.value {
+                t.kind = TableKind::Header;
+            }
+        }
+        Some(_) => {
+            let name = key.iter().map(|(k, _)| &**k).collect::<Vec<_>>().join(".");
+            return Err((*last_span, format!("duplicate definition of `{name}`")));
+        }
+    }
+    Ok(())
+}
+
+/// Inserts a key-value pair into `table`, creating tables for dotted keys.
+fn insert(
+    mut table: &mut Table,
+    key: Key,
+    value: Value,
+    value_span: Span,
+) -> Result<(), ParseError> {
+    let mut key = key.into_iter().peekable();
+    while let Some((name, span)) = key.next() {
+        if key.peek().is_none() {
+            if table.entry(&name).is_some() {
+                return Err((span, format!("duplicate key `{name}`")));
+            }
+            table.push(name, span, value, value_span);
+            return Ok(());
+        }
+
+        if table.entry(&name).is_none() {
+            table.push(
+                name.clone(),
+                span,
+                Value::Table(Table::new(TableKind::Dotted)),
+                span,
+            );
+        }
+        table = match &mut table.entry_mut(&name).unwrap().value {
+            Value::Table(t) if t.kind == TableKind::Dotted => t,
+            Value::Table(_) => {
+                return Err((span, format!("cannot add keys to table `{name}` here")));
+            }
+            _ => return Err((span, format!("key `{name}` is not a table"))),
+        };
     }
+    unreachable!()
 }
 
-fn start_of_section_or_array(mut line: &str) -> Option<&str> {
-    line = line.trim();
+fn parse_number(text: &str) -> Option<Value> {
+    match text {
+        "inf" | "+inf" => return Some(Value::Float(f64::INFINITY)),
+        "-inf" => return Some(Value::Float(f64::NEG_INFINITY)),
+        "nan" | "+nan" => return Some(Value::Float(f64::NAN)),
+        "-nan" => return Some(Value::Float(-f64::NAN)),
+        _ => {}
+    }
+
+    for (prefix, radix) in [("0x", 16), ("0o", 8), ("0b", 2)] {
+        if let Some(digits) = text.strip_prefix(prefix) {
+            if !is_valid_digits(digits, radix) {
+                return None;
+            }
+            return i64::from_str_radix(&digits.replace('_', ""), radix)
+                .ok()
+                .map(Value::Integer);
+        }
+    }
+
+    let unsigned = text.strip_prefix(['+', '-']).unwrap_or(text);
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((m, e)) => (m, Some(e)),
+        None => (unsigned, None),
+    };
+    let (int, frac) = match mantissa.split_once('.') {
+        Some((i, f)) => (i, Some(f)),
+        None => (mantissa, None),
+    };
 
-    if !line.starts_with('[') {
+    if !is_valid_digits(int, 10) || (int.starts_with('0') && int.len() > 1) {
+        return None;
+    }
+    if frac.is_some_and(|f| !is_valid_digits(f, 10)) {
         return None;
     }
+    if let Some(exp) = exponent {
+        if !is_valid_digits(exp.strip_prefix(['+', '-']).unwrap_or(exp), 10) {
+            return None;
+        }
+    }
 
-    if line.starts_with("[[") {
-        line = &line[2..];
+    let cleaned = text.replace('_', "");
+    if frac.is_none() && exponent.is_none() {
+        cleaned.parse().ok().map(Value::Integer)
     } else {
-        line = &line[1..];
+        cleaned.parse().ok().map(Value::Float)
     }
+}
 
-    let (name, _) = line.split_once(']')?;
-    Some(name.trim())
+/// Checks that `digits` is a non-empty sequence of digits, where underscores may only appear
+/// between two digits.
+fn is_valid_digits(digits: &str, radix: u32) -> bool {
+    !digits.is_empty()
+        && !digits.starts_with('_')
+        && !digits.ends_with('_')
+        && !digits.contains("__")
+        && digits.chars().all(|c| c == '_' || c.is_digit(radix))
+}
+
+fn parse_datetime(text: &str) -> Option<Datetime> {
+    if text.as_bytes().get(2) == Some(&b':') {
+        return Some(Datetime {
+            date: None,
+            time: Some(parse_time(text)?),
+            offset: None,
+        });
+    }
+
+    let (date, time) = text.split_at(text.len().min(10));
+    let date = Some(parse_date(date)?);
+    match time.chars().next() {
+        None => Some(Datetime {
+            date,
+            time: None,
+            offset: None,
+        }),
+        Some('T' | 't' | ' ') => {
+            let time = &time[1..];
+            let offset_start = time
+                .char_indices()
+                .skip(8)
+                .find(|(_, c)| matches!(c, 'Z' | 'z' | '+' | '-'))
+                .map_or(time.len(), |(i, _)| i);
+            let (time, offset) = time.split_at(offset_start);
+            Some(Datetime {
+                date,
+                time: Some(parse_time(time)?),
+                offset: match offset {
+                    "" => None,
+                    _ => Some(parse_offset(offset)?),
+                },
+            })
+        }
+        Some(_) => None,
+    }
+}
+
+/// Parses a `YYYY-MM-DD` date.
+pub fn parse_date(date: &str) -> Option<Date> {
+    let [y0, y1, y2, y3, b'-', m0, m1, b'-', d0, d1] = *date.as_bytes() else {
+        return None;
+    };
+    let year = parse_digits(&[y0, y1, y2, y3])?;
+    let month = parse_digits(&[m0, m1])?;
+    let day = parse_digits(&[d0, d1])?;
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if !(1..=12).contains(&month) || !(1..=days_in_month).contains(&day) {
+        return None;
+    }
+    Some(Date {
+        year: year as u16,
+        month: month as u8,
+        day: day as u8,
+    })
+}
+
+fn parse_time(time: &str) -> Option<Time> {
+    let (hms, frac) = match time.split_once('.') {
+        Some((hms, frac)) => (hms, Some(frac)),
+        None => (time, None),
+    };
+    let [h0, h1, b':', m0, m1, b':', s0, s1] = *hms.as_bytes() else {
+        return None;
+    };
+    let hour = parse_digits(&[h0, h1])?;
+    let minute = parse_digits(&[m0, m1])?;
+    let second = parse_digits(&[s0, s1])?;
+    if hour >= 24 || minute >= 60 || second > 60 {
+        return None;
+    }
+    let nanosecond = match frac {
+        None => 0,
+        Some("") => return None,
+        Some(f) => {
+            // Precision beyond nanoseconds is truncated.
+            let digits = &f.as_bytes()[..f.len().min(9)];
+            let value = parse_digits(digits)?;
+            if !f.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            value * 10u32.pow(9 - digits.len() as u32)
+        }
+    };
+    Some(Time {
+        hour: hour as u8,
+        minute: minute as u8,
+        second: second as u8,
+        nanosecond,
+    })
+}
+
+fn parse_offset(offset: &str) -> Option<Offset> {
+    if offset == "Z" || offset == "z" {
+        return Some(Offset::Z);
+    }
+    let [sign @ (b'+' | b'-'), h0, h1, b':', m0, m1] = *offset.as_bytes() else {
+        return None;
+    };
+    let hours = parse_digits(&[h0, h1])?;
+    let minutes = parse_digits(&[m0, m1])?;
+    if hours >= 24 || minutes >= 60 {
+        return None;
+    }
+    let minutes = (hours * 60 + minutes) as i16;
+    Some(Offset::Minutes(if sign == b'-' {
+        -minutes
+    } else {
+        minutes
+    }))
+}
+
+fn parse_digits(digits: &[u8]) -> Option<u32> {
+    digits.iter().try_fold(0, |acc, &b| {
+        b.is_ascii_digit().then(|| acc * 10 + u32::from(b - b'0'))
+    })
 }
 
 #[cfg(test)]
@@ -127,43 +1443,26 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_next_line() {
+    fn test_section_names() {
         #[track_caller]
-        fn check(mut input: &str, line: Option<&str>, rest: &str) {
-            assert_eq!(next_line(&mut input), line);
-            assert_eq!(input, rest);
+        fn check(input: &str, name: &str) {
+            let toml = Toml::parse(input).unwrap();
+            assert_eq!(toml.sections()[0].0, name);
         }
 
-        check("a\nb", Some("a"), "b");
-        check("abc", Some("abc"), "");
-        check("\n", Some(""), "");
-        check("\nxyz", Some(""), "xyz");
-        check("", None, "");
-    }
-
-    #[test]
-    fn test_start_of_section() {
-        assert_eq!(start_of_section_or_array("[section]"), Some("section"));
-        assert_eq!(start_of_section_or_array("[[array]]"), Some("array"));
-        assert_eq!(start_of_section_or_array(" [section]"), Some("section"));
-        assert_eq!(start_of_section_or_array(" [[array]]"), Some("array"));
-        assert_eq!(
-            start_of_section_or_array("[section] # comment"),
-            Some("section")
-        );
-        assert_eq!(
-            start_of_section_or_array("[[array]] # comment"),
-            Some("array")
-        );
-        assert_eq!(
-            start_of_section_or_array(r#"[bla."*".asd]"#),
-            Some(r#"bla."*".asd"#)
-        );
+        check("[section]", "section");
+        check("[[array]]", "array");
+        check(" [section]", "section");
+        check(" [[array]]", "array");
+        check("[ section ]", "section");
+        check("[section] # comment", "section");
+        check("[[array]] # comment", "array");
+        check(r#"[bla."*".asd]"#, r#"bla."*".asd"#);
     }
 
     #[test]
     fn test_sections() {
-        let toml = Toml(
+        let toml = Toml::parse(
             r#"
             [[array]]
             a = 0
@@ -177,7 +1476,8 @@ mod tests {
             [target.x86_64-pc-windows-gnu."*".dependencies]
             eof = true
             "#,
-        );
+        )
+        .unwrap();
 
         let sections = toml.sections();
         expect![[r#"
@@ -210,4 +1510,605 @@ mod tests {
         "#]]
         .assert_debug_eq(&sections);
     }
+
+    #[test]
+    fn test_set_path() {
+        #[track_caller]
+        fn check(input: &str, path: &[&str], value: Value, expect: expect_test::Expect) {
+            let toml = Toml::parse(input).unwrap();
+            expect.assert_eq(&toml.set_path(path, &value).unwrap());
+        }
+
+        let manifest = r#"# My package
+[package]
+name = "mypkg"
+version = "0.1.0"   # bumped by CI
+edition.workspace = true
+
+[dependencies]
+# local
+dep = { path = "../dep", version = "0.1.0" }
+empty = {}
+other.path = "../other"
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+"#;
+        let version = || Value::Str("0.2.0".into());
+        check(
+            manifest,
+            &["package", "version"],
+            version(),
+            expect![[r#"
+                # My package
+                [package]
+                name = "mypkg"
+                version = "0.2.0"   # bumped by CI
+                edition.workspace = true
+
+                [dependencies]
+                # local
+                dep = { path = "../dep", version = "0.1.0" }
+                empty = {}
+                other.path = "../other"
+
+                [target.'cfg(unix)'.dependencies]
+                libc = "0.2"
+            "#]],
+        );
+        check(
+            manifest,
+            &["dependencies", "dep", "version"],
+            version(),
+            expect![[r##"
+                # My package
+                [package]
+                name = "mypkg"
+                version = "0.1.0"   # bumped by CI
+                edition.workspace = true
+
+                [dependencies]
+                # local
+                dep = { path = "../dep", version = "0.2.0" }
+                empty = {}
+                other.path = "../other"
+
+                [target.'cfg(unix)'.dependencies]
+                libc = "0.2"
+            "##]],
+        );
+        check(
+            manifest,
+            &["dependencies", "dep", "features"],
+            Value::Array(vec![Value::Str("std".into())]),
+            expect![[r##"
+                # My package
+                [package]
+                name = "mypkg"
+                version = "0.1.0"   # bumped by CI
+                edition.workspace = true
+
+                [dependencies]
+                # local
+                dep = { path = "../dep", version = "0.1.0", features = ["std"] }
+                empty = {}
+                other.path = "../other"
+
+                [target.'cfg(unix)'.dependencies]
+                libc = "0.2"
+            "##]],
+        );
+        check(
+            manifest,
+            &["dependencies", "empty", "version"],
+            version(),
+            expect![[r##"
+                # My package
+                [package]
+                name = "mypkg"
+                version = "0.1.0"   # bumped by CI
+                edition.workspace = true
+
+                [dependencies]
+                # local
+                dep = { path = "../dep", version = "0.1.0" }
+                empty = { version = "0.2.0" }
+                other.path = "../other"
+
+                [target.'cfg(unix)'.dependencies]
+                libc = "0.2"
+            "##]],
+        );
+        check(
+            manifest,
+            &["dependencies", "other", "version"],
+            version(),
+            expect![[r##"
+                # My package
+                [package]
+                name = "mypkg"
+                version = "0.1.0"   # bumped by CI
+                edition.workspace = true
+
+                [dependencies]
+                # local
+                dep = { path = "../dep", version = "0.1.0" }
+                empty = {}
+                other.path = "../other"
+                other.version = "0.2.0"
+
+                [target.'cfg(unix)'.dependencies]
+                libc = "0.2"
+            "##]],
+        );
+        check(
+            manifest,
+            &["package", "description"],
+            Value::Str("a \"quoted\" description".into()),
+            expect![[r##"
+                # My package
+                [package]
+                name = "mypkg"
+                version = "0.1.0"   # bumped by CI
+                edition.workspace = true
+                description = "a \"quoted\" description"
+
+                [dependencies]
+                # local
+                dep = { path = "../dep", version = "0.1.0" }
+                empty = {}
+                other.path = "../other"
+
+                [target.'cfg(unix)'.dependencies]
+                libc = "0.2"
+            "##]],
+        );
+        check(
+            manifest,
+            &["target", "cfg(unix)", "dependencies", "nix"],
+            Value::Str("0.29".into()),
+            expect![[r##"
+                # My package
+                [package]
+                name = "mypkg"
+                version = "0.1.0"   # bumped by CI
+                edition.workspace = true
+
+                [dependencies]
+                # local
+                dep = { path = "../dep", version = "0.1.0" }
+                empty = {}
+                other.path = "../other"
+
+                [target.'cfg(unix)'.dependencies]
+                libc = "0.2"
+                nix = "0.29"
+            "##]],
+        );
+        check(
+            manifest,
+            &["target", "cfg(windows)", "dependencies", "winapi"],
+            Value::Str("0.3".into()),
+            expect![[r##"
+                # My package
+                [package]
+                name = "mypkg"
+                version = "0.1.0"   # bumped by CI
+                edition.workspace = true
+
+                [dependencies]
+                # local
+                dep = { path = "../dep", version = "0.1.0" }
+                empty = {}
+                other.path = "../other"
+
+                [target.'cfg(unix)'.dependencies]
+                libc = "0.2"
+
+                [target."cfg(windows)".dependencies]
+                winapi = "0.3"
+            "##]],
+        );
+        check(
+            manifest,
+            &["workspace", "package", "version"],
+            version(),
+            expect![[r##"
+                # My package
+                [package]
+                name = "mypkg"
+                version = "0.1.0"   # bumped by CI
+                edition.workspace = true
+
+                [dependencies]
+                # local
+                dep = { path = "../dep", version = "0.1.0" }
+                empty = {}
+                other.path = "../other"
+
+                [target.'cfg(unix)'.dependencies]
+                libc = "0.2"
+
+                [workspace.package]
+                version = "0.2.0"
+            "##]],
+        );
+        let toml = Toml::parse("[workspace]\r\n  members = []\r\n  resolver = \"2\"").unwrap();
+        let exclude = Value::Array(vec![Value::Str("target".into())]);
+        assert_eq!(
+            toml.set_path(&["workspace", "exclude"], &exclude).unwrap(),
+            "[workspace]\r\n  members = []\r\n  resolver = \"2\"\r\n  exclude = [\"target\"]\r\n",
+        );
+
+        let toml = Toml::parse(manifest).unwrap();
+        let error = |path: &[&str]| toml.set_path(path, &version()).unwrap_err().message;
+        expect!["cannot replace table `dependencies`"].assert_eq(&error(&["dependencies"]));
+        expect!["`package.name` is a string, not a table"]
+            .assert_eq(&error(&["package", "name", "x"]));
+        expect!["cannot replace table `package.edition`"]
+            .assert_eq(&error(&["package", "edition"]));
+    }
+
+    #[test]
+    fn test_parse() {
+        let toml = Toml::parse(
+            r#"
+# comment
+str = "a\tb \u00e9 \"q\""
+literal = 'C:\path'
+multiline = """
+first \
+    second"""
+multiline-literal = '''
+raw \n ""'''
+ints = [1_000, -17, 0xff, 0o17, 0b101]
+floats = [1.5, -2e3, inf, nan]
+dates = [1979-05-27T07:32:00Z, 1979-05-27 07:32:00.5-07:00, 1979-05-27, 07:32:00]
+nested = [[1, 2], ["a"],]
+inline = { a = 1, b.c = true }
+"quoted key" = false
+site."google.com" = true
+
+[package]
+name = "pkg"
+version.workspace = true
+
+[[bin]]
+name = "a"
+[[bin]]
+name = "b"
+[bin.extra]
+x = 1
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+"#,
+        )
+        .unwrap();
+
+        expect![[r#"
+            {
+                "str": Str(
+                    "a\tb é \"q\"",
+                ),
+                "literal": Str(
+                    "C:\\path",
+                ),
+                "multiline": Str(
+                    "first second",
+                ),
+                "multiline-literal": Str(
+                    "raw \\n \"\"",
+                ),
+                "ints": Array(
+                    [
+                        Integer(
+                            1000,
+                        ),
+                        Integer(
+                            -17,
+                        ),
+                        Integer(
+                            255,
+                        ),
+                        Integer(
+                            15,
+                        ),
+                        Integer(
+                            5,
+                        ),
+                    ],
+                ),
+                "floats": Array(
+                    [
+                        Float(
+                            1.5,
+                        ),
+                        Float(
+                            -2000.0,
+                        ),
+                        Float(
+                            inf,
+                        ),
+                        Float(
+                            NaN,
+                        ),
+                    ],
+                ),
+                "dates": Array(
+                    [
+                        Datetime(
+                            1979-05-27T07:32:00Z,
+                        ),
+                        Datetime(
+                            1979-05-27T07:32:00.5-07:00,
+                        ),
+                        Datetime(
+                            1979-05-27,
+                        ),
+                        Datetime(
+                            07:32:00,
+                        ),
+                    ],
+                ),
+                "nested": Array(
+                    [
+                        Array(
+                            [
+                                Integer(
+                                    1,
+                                ),
+                                Integer(
+                                    2,
+                                ),
+                            ],
+                        ),
+                        Array(
+                            [
+                                Str(
+                                    "a",
+                                ),
+                            ],
+                        ),
+                    ],
+                ),
+                "inline": Table(
+                    {
+                        "a": Integer(
+                            1,
+                        ),
+                        "b": Table(
+                            {
+                                "c": Bool(
+                                    true,
+                                ),
+                            },
+                        ),
+                    },
+                ),
+                "quoted key": Bool(
+                    false,
+                ),
+                "site": Table(
+                    {
+                        "google.com": Bool(
+                            true,
+                        ),
+                    },
+                ),
+                "package": Table(
+                    {
+                        "name": Str(
+                            "pkg",
+                        ),
+                        "version": Table(
+                            {
+                                "workspace": Bool(
+                                    true,
+                                ),
+                            },
+                        ),
+                    },
+                ),
+                "bin": Array(
+                    [
+                        Table(
+                            {
+                                "name": Str(
+                                    "a",
+                                ),
+                            },
+                        ),
+                        Table(
+                            {
+                                "name": Str(
+                                    "b",
+                                ),
+                                "extra": Table(
+                                    {
+                                        "x": Integer(
+                                            1,
+                                        ),
+                                    },
+                                ),
+                            },
+                        ),
+                    ],
+                ),
+                "target": Table(
+                    {
+                        "cfg(unix)": Table(
+                            {
+                                "dependencies": Table(
+                                    {
+                                        "libc": Str(
+                                            "0.2",
+                                        ),
+                                    },
+                                ),
+                            },
+                        ),
+                    },
+                ),
+            }
+        "#]]
+        .assert_debug_eq(toml.root());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        #[track_caller]
+        fn check(input: &str, expect: expect_test::Expect) {
+            let error = Toml::parse(input).map(|_| ()).unwrap_err();
+            let error = format!(
+                "{} at line {}, column {}",
+                error.message, error.line, error.column
+            );
+            expect.assert_eq(&error);
+        }
+
+        check(
+            "a = \"unterminated",
+            expect!["unterminated string at line 1, column 18"],
+        );
+        check(
+            "a = 1\na = 2",
+            expect!["duplicate key `a` at line 2, column 1"],
+        );
+        check(
+            "[a]\n[a]",
+            expect!["duplicate definition of `a` at line 2, column 2"],
+        );
+        check(
+            "a.b = 1\n[a]",
+            expect!["duplicate definition of `a` at line 2, column 2"],
+        );
+        check(
+            "a = {}\n[a.b]",
+            expect!["cannot extend inline table `a` at line 2, column 2"],
+        );
+        check(
+            "a = [1]\n[[a]]",
+            expect!["duplicate definition of `a` at line 2, column 3"],
+        );
+        check(
+            "a = 1 b = 2",
+            expect!["expected newline, found `b` at line 1, column 7"],
+        );
+        check("a = 01", expect!["invalid value `01` at line 1, column 5"]);
+        check(
+            "a = 1__0",
+            expect!["invalid value `1__0` at line 1, column 5"],
+        );
+        check(
+            "a = 1979-02-30",
+            expect!["invalid value `1979-02-30` at line 1, column 5"],
+        );
+        check(
+            "a = { b = 1, }",
+            expect!["expected a key, found `}` at line 1, column 14"],
+        );
+        check(
+            "a = \"\\q\"",
+            expect!["invalid escape sequence at line 1, column 6"],
+        );
+        check(
+            "= 1",
+            expect!["expected a key, found `=` at line 1, column 1"],
+        );
+        check(
+            "description = é",
+            expect!["expected a value, found `é` at line 1, column 15"],
+        );
+        check(
+            "\u{feff}[package]",
+            expect!["expected a key, found `﻿` at line 1, column 1"],
+        );
+    }
+
+    #[test]
+    fn test_get_path() {
+        #[track_caller]
+        fn check(input: &str) {
+            let toml = Toml::parse(input).unwrap();
+            let version = toml.get_path(&["package", "version"]).unwrap();
+            assert_eq!(version.as_str(), Some("1.2.3"));
+        }
+
+        check("[package]\nversion = \"1.2.3\"");
+        check("package.version = \"1.2.3\"");
+        check("package = { version = \"1.2.3\" }");
+        check("[package]\n'version' = \"1.2.3\"");
+        check("[workspace]\n[package]\nname = \"x\"\nversion = \"1.2.3\"\n[dependencies]");
+
+        let toml = Toml::parse(
+            r#"
+            [target.'cfg(target_os = "linux")'.dependencies]
+            libc = "0.2"
+            [workspace]
+            package.version = "1.0.0"
+            "#,
+        )
+        .unwrap();
+        let libc = toml.get_path(&[
+            "target",
+            r#"cfg(target_os = "linux")"#,
+            "dependencies",
+            "libc",
+        ]);
+        assert_eq!(libc.unwrap().as_str(), Some("0.2"));
+        expect![[r#"can't find `package.version`"#]]
+            .assert_eq(&toml.get_path(&["package", "version"]).unwrap_err().message);
+        expect![[r#"can't find `target."cfg(unix)"`"#]]
+            .assert_eq(&toml.get_path(&["target", "cfg(unix)"]).unwrap_err().message);
+    }
+
+    #[test]
+    fn test_typed_accessors() {
+        let toml = Toml::parse(
+            r#"
+            keywords = ["a", "b"]
+            mixed = ["a", 1]
+            int = 42
+            float = 0.5
+            bool = true
+            odt = 1979-05-27T00:32:00.999999-07:00
+            ldt = 1979-05-27 07:32:00
+            date = 2024-02-29
+            time = 00:32:00.5
+            dep = { path = "x" }
+            [table]
+            "#,
+        )
+        .unwrap();
+        let get = |key| toml.get_path(&[key]).unwrap();
+
+        assert_eq!(get("keywords").as_str_array(), Some(vec!["a", "b"]));
+        assert_eq!(get("mixed").as_str_array(), None);
+        assert_eq!(get("mixed").as_array().map(<[_]>::len), Some(2));
+        assert_eq!(get("int").as_integer(), Some(42));
+        assert_eq!(get("int").as_float(), Some(42.0));
+        assert_eq!(get("float").as_float(), Some(0.5));
+        assert_eq!(get("bool").as_bool(), Some(true));
+        assert_eq!(get("bool").as_str(), None);
+        assert_eq!(get("dep").type_name(), "table");
+        assert!(get("dep").as_table().unwrap().is_inline());
+        assert!(!get("table").as_table().unwrap().is_inline());
+
+        let odt = get("odt").as_datetime().unwrap();
+        assert_eq!(odt.offset, Some(Offset::Minutes(-7 * 60)));
+        assert_eq!(odt.time.unwrap().nanosecond, 999_999_000);
+        assert!(get("date").as_datetime().unwrap().date > odt.date);
+        expect![[r#"
+            [
+                "1979-05-27T00:32:00.999999-07:00",
+                "1979-05-27T07:32:00",
+                "2024-02-29",
+                "00:32:00.5",
+            ]
+        "#]]
+        .assert_debug_eq(
+            &["odt", "ldt", "date", "time"].map(|key| get(key).as_datetime().unwrap().to_string()),
+        );
+    }
 }
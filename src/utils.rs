@@ -6,7 +6,7 @@ pub fn next_line<'a>(text: &mut &'a str) -> Option<&'a str> {
         }
         None => {
             if text.is_empty() {
-                return None;
+                None
             } else {
                 let line = *text;
                 *text = "";